@@ -0,0 +1,72 @@
+use std::sync::Arc;
+use std::time::Instant;
+
+use crate::map::MapSpawn;
+use euclid::{Point2D, Size2D};
+
+use crate::{Drive, GameRules, GameState, Input, Level, Map, Turn, Weapon};
+
+/// Tanks in each benchmarked match
+const TANK_COUNTS: [usize; 4] = [10, 100, 250, 500];
+/// Ticks run before measuring, so the bullet count has built up
+const WARMUP_TICKS: usize = 120;
+const MEASURED_TICKS: usize = 600;
+
+/// Prints how many ticks per second the simulation manages as matches grow
+///
+/// Every tank drives in circles while spraying the machine gun, so the bullet count grows with
+/// the tank count. Run with `cargo run --release --features bench -- bench`, adding
+/// `--bullets-collide` to include bullet interception.
+pub fn run_bench() {
+    let rules = GameRules {
+        bullets_collide: std::env::args().any(|x| x == "--bullets-collide"),
+        ..GameRules::default()
+    };
+    for &tanks in TANK_COUNTS.iter() {
+        let mut state = arena(tanks, rules.clone());
+        for i in 0..tanks {
+            let player = state.add_player(format!("bot {}", i));
+            state.players[player].as_mut().unwrap().input = Input {
+                drive: Some(Drive::Forward),
+                rotate: Some(if i % 2 == 0 { Turn::Left } else { Turn::Right }),
+                turret: Some(Turn::Left),
+                fire: true,
+                weapon: Weapon::MachineGun,
+                seq: 0,
+            };
+        }
+        for _ in 0..WARMUP_TICKS {
+            state.advance();
+        }
+        let start = Instant::now();
+        for _ in 0..MEASURED_TICKS {
+            state.advance();
+        }
+        let elapsed = start.elapsed().as_secs_f32();
+        println!(
+            "{:>4} tanks, {:>5} bullets: {:>8.1} ticks/s",
+            tanks,
+            state.bullets.len(),
+            MEASURED_TICKS as f32 / elapsed
+        );
+    }
+}
+
+/// A square arena with a spawn point for each tank, laid out in a grid
+fn arena(tanks: usize, rules: GameRules) -> GameState {
+    const SPACING: i64 = 150;
+    let side = (tanks as f64).sqrt().ceil() as i64;
+    let spawns = (0..tanks as i64)
+        .map(|i| MapSpawn {
+            position: Point2D::new(i % side, i / side) * SPACING
+                + Size2D::new(SPACING, SPACING) / 2,
+            angle: 0.0,
+            team: None,
+        })
+        .collect();
+    let map = Map {
+        spawns,
+        ..Map::empty("bench", Size2D::new(side * SPACING, side * SPACING))
+    };
+    GameState::with_level(Arc::new(Level::from(&map)), Default::default(), rules)
+}
@@ -1,13 +1,13 @@
 use std::any::Any;
 use std::time::Duration;
 
-use super::{render_frame, EventLoop, Renderer};
-use crate::{Bullet, Drive, GameState, Input, Tank, Turn, GM_SCALE};
+use super::{bullet_style, render_frame, scoreboard_lines, shade, EventLoop, Renderer};
+use crate::{Bullet, Drive, GameState, Input, Scoreboard, Tank, Turn, Wall, Weapon, GM_SCALE};
 
 use tokio::sync::watch;
 
 use druid_shell::kurbo::{Affine, Ellipse, Rect, Size};
-use druid_shell::piet::{self, Color, Piet, RenderContext};
+use druid_shell::piet::{self, Color, FontFamily, Piet, RenderContext, Text, TextLayoutBuilder};
 use druid_shell::{Application, Code, KeyEvent, Region, WinHandler, WindowBuilder, WindowHandle};
 
 pub struct DruidEventLoop {
@@ -27,6 +27,7 @@ impl EventLoop for DruidEventLoop {
             input: Input,
             send_input: watch::Sender<Input>,
             size: Size,
+            show_scoreboard: bool,
         }
         impl WinHandler for WHandler {
             fn connect(&mut self, handle: &WindowHandle) {
@@ -41,12 +42,15 @@ impl EventLoop for DruidEventLoop {
                 piet.clear(Color::rgb8(0, 0, 0));
                 piet.transform(Affine::scale_non_uniform(1.0, -1.0));
                 piet.transform(Affine::translate((0.0, -self.size.height)));
-                let mut r = PietRenderer { piet };
+                let mut r = PietRenderer {
+                    piet,
+                    height: self.size.height,
+                };
                 if let Err(_) = self.send_input.send(self.input.clone()) {
                     self.request_close();
                     return;
                 }
-                if let Err(_) = render_frame(&mut r, &mut self.recv_state) {
+                if let Err(_) = render_frame(&mut r, &mut self.recv_state, self.show_scoreboard) {
                     self.request_close();
                 }
             }
@@ -65,6 +69,11 @@ impl EventLoop for DruidEventLoop {
                     Code::KeyJ => self.input.turret = Some(Turn::Left),
                     Code::KeyL => self.input.turret = Some(Turn::Right),
                     Code::Space => self.input.fire = true,
+                    Code::Tab => self.show_scoreboard = true,
+                    Code::Digit1 => self.input.weapon = Weapon::Cannon,
+                    Code::Digit2 => self.input.weapon = Weapon::MachineGun,
+                    Code::Digit3 => self.input.weapon = Weapon::Shotgun,
+                    Code::Digit4 => self.input.weapon = Weapon::HeavyShell,
                     _ => {}
                 };
                 true
@@ -75,6 +84,7 @@ impl EventLoop for DruidEventLoop {
                     Code::KeyA | Code::KeyD => self.input.rotate = None,
                     Code::KeyJ | Code::KeyL => self.input.turret = None,
                     Code::Space => self.input.fire = false,
+                    Code::Tab => self.show_scoreboard = false,
                     _ => {}
                 }
             }
@@ -94,6 +104,7 @@ impl EventLoop for DruidEventLoop {
             input: Input::default(),
             send_input,
             size,
+            show_scoreboard: false,
         }));
         let win = wb.build().unwrap();
         win.show();
@@ -111,20 +122,39 @@ impl EventLoop for DruidEventLoop {
 
 struct PietRenderer<'a, 'b> {
     piet: &'a mut Piet<'b>,
+    height: f64,
 }
 
 impl Renderer for PietRenderer<'_, '_> {
-    fn draw_tank(&mut self, tank: &Tank) {
+    fn draw_wall(&mut self, wall: &Wall) {
+        let pos = (wall.center / GM_SCALE).to_f64();
+        let size = (wall.half_size / GM_SCALE).to_f64() * 2.0;
+        self.piet
+            .with_save(|piet| {
+                piet.transform(Affine::translate((pos.x, pos.y)));
+                piet.transform(Affine::rotate(wall.angle.to_angle().to_f64().radians));
+                piet.fill(
+                    Rect::from_center_size((0.0, 0.0), (size.width, size.height)),
+                    &Color::rgb8(128, 128, 128),
+                );
+                Ok(())
+            })
+            .unwrap();
+    }
+    fn draw_tank(&mut self, tank: &Tank, size: f32, colour: (u8, u8, u8)) {
+        let size = size as f64;
+        let (r, g, b) = colour;
+        let (tr, tg, tb) = shade(colour);
         self.piet.save().unwrap();
         let pos = (tank.position / GM_SCALE).to_f64();
         self.piet.transform(Affine::translate((pos.x, pos.y)));
 
         self.piet
             .with_save(|piet| {
-                piet.transform(Affine::rotate(tank.angle.to_f64().radians));
+                piet.transform(Affine::rotate(tank.angle.to_angle().to_f64().radians));
                 piet.fill(
-                    Rect::from_center_size((0.0, 0.0), (40.0, 40.0)),
-                    &Color::rgb8(0, 255, 0),
+                    Rect::from_center_size((0.0, 0.0), (size * 2.0, size * 2.0)),
+                    &Color::rgb8(r, g, b),
                 );
                 Ok(())
             })
@@ -132,23 +162,35 @@ impl Renderer for PietRenderer<'_, '_> {
 
         self.piet
             .with_save(|piet| {
-                piet.transform(Affine::rotate(tank.turret_angle.to_f64().radians));
+                piet.transform(Affine::rotate(
+                    tank.turret_angle.to_angle().to_f64().radians,
+                ));
                 piet.fill(
-                    Rect::from_origin_size((0.0, -5.0), (40.0, 10.0)),
-                    &Color::rgb8(0, 200, 0),
+                    Rect::from_origin_size((0.0, -5.0), (size * 2.0, 10.0)),
+                    &Color::rgb8(tr, tg, tb),
                 );
                 Ok(())
             })
             .unwrap();
 
         self.piet.fill(
-            Rect::from_origin_size((-30.0, -45.0), (60.0, 5.0)),
+            Rect::from_origin_size((-30.0, -size - 25.0), (60.0, 5.0)),
             &Color::rgb8(255, 0, 0),
         );
         self.piet.fill(
-            Rect::from_origin_size((-30.0, -45.0), (tank.health as f64 * (60.0 / 100.0), 5.0)),
+            Rect::from_origin_size(
+                (-30.0, -size - 25.0),
+                (tank.health_fraction() as f64 * 60.0, 5.0),
+            ),
             &Color::rgb8(0, 255, 0),
         );
+        self.piet.fill(
+            Rect::from_origin_size(
+                (-30.0, -size - 32.0),
+                (60.0 * tank.weapon.magazine_fraction() as f64, 3.0),
+            ),
+            &Color::rgb8(255, 200, 0),
+        );
 
         self.piet.restore().unwrap();
     }
@@ -162,10 +204,42 @@ impl Renderer for PietRenderer<'_, '_> {
         //    return;
         //}
         let pos = (bullet.position / GM_SCALE).to_f64();
+        let (length, (r, g, b)) = bullet_style(bullet);
+        let length = length as f64;
         self.piet.fill(
-            Ellipse::new((pos.x, pos.y), (20.0, 5.0), bullet.angle.to_f64().radians),
-            &Color::rgb8(255, 0, 0),
+            Ellipse::new(
+                (pos.x, pos.y),
+                (length, length / 4.0),
+                bullet.angle.to_angle().to_f64().radians,
+            ),
+            &Color::rgb8(r, g, b),
         );
     }
+    fn draw_scoreboard(&mut self, scoreboard: &Scoreboard) {
+        let height = self.height;
+        self.piet
+            .with_save(|piet| {
+                // undo the y flip so text is drawn the right way up
+                piet.transform(Affine::translate((0.0, height)) * Affine::FLIP_Y);
+                piet.fill(
+                    Rect::from_origin_size(
+                        (40.0, 40.0),
+                        (520.0, 40.0 + 24.0 * scoreboard.rows.len() as f64),
+                    ),
+                    &Color::rgba8(0, 0, 0, 200),
+                );
+                for (i, line) in scoreboard_lines(scoreboard).into_iter().enumerate() {
+                    let layout = piet
+                        .text()
+                        .new_text_layout(line)
+                        .font(FontFamily::MONOSPACE, 18.0)
+                        .text_color(Color::WHITE)
+                        .build()?;
+                    piet.draw_text(&layout, (56.0, 52.0 + 24.0 * i as f64));
+                }
+                Ok(())
+            })
+            .unwrap();
+    }
     fn present_frame(&mut self) {}
 }
@@ -1,7 +1,7 @@
 use std::time::Duration;
 
 use super::{render_frame, EventLoop, RaqoteRenderer, Renderer};
-use crate::{Bullet, Drive, GameState, Input, Tank, Turn};
+use crate::{Bullet, Drive, GameState, Input, Scoreboard, Tank, Turn, Wall, Weapon};
 
 use tokio::sync::watch;
 
@@ -13,6 +13,7 @@ pub struct MinifbEventLoop {
     frame: Vec<u32>,
     width: usize,
     height: usize,
+    weapon: Weapon,
 }
 
 impl EventLoop for MinifbEventLoop {
@@ -50,16 +51,26 @@ impl EventLoop for MinifbEventLoop {
                 _ => None,
             };
             let fire = self.window.is_key_down(Key::Space);
+            for (key, weapon) in [Key::Key1, Key::Key2, Key::Key3, Key::Key4]
+                .iter()
+                .zip(&Weapon::ALL)
+            {
+                if self.window.is_key_down(*key) {
+                    self.weapon = *weapon;
+                }
+            }
             if let Err(_) = send_input.send(Input {
                 drive,
                 rotate,
                 turret,
                 fire,
+                weapon: self.weapon,
                 seq: 0,
             }) {
                 break;
             }
-            if let Err(_) = render_frame(&mut self, &mut recv_state) {
+            let show_scoreboard = self.window.is_key_down(Key::Tab);
+            if let Err(_) = render_frame(&mut self, &mut recv_state, show_scoreboard) {
                 break;
             }
         }
@@ -88,17 +99,24 @@ impl EventLoop for MinifbEventLoop {
             frame,
             width,
             height,
+            weapon: Weapon::default(),
         }
     }
 }
 
 impl Renderer for MinifbEventLoop {
-    fn draw_tank(&mut self, tank: &Tank) {
-        self.raqote.draw_tank(tank);
+    fn draw_wall(&mut self, wall: &Wall) {
+        self.raqote.draw_wall(wall);
+    }
+    fn draw_tank(&mut self, tank: &Tank, size: f32, colour: (u8, u8, u8)) {
+        self.raqote.draw_tank(tank, size, colour);
     }
     fn draw_bullet(&mut self, bullet: &Bullet) {
         self.raqote.draw_bullet(bullet);
     }
+    fn draw_scoreboard(&mut self, scoreboard: &Scoreboard) {
+        self.raqote.draw_scoreboard(scoreboard);
+    }
     fn present_frame(&mut self) {
         let pixels = self.raqote.get_data_u8();
         for (dest, [sb, sg, sr, sa]) in self.frame.iter_mut().zip(pixels.array_chunks()) {
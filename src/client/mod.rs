@@ -1,17 +1,21 @@
 use futures::{try_join, FutureExt, Sink, SinkExt, Stream, StreamExt};
-use futures::lock::BiLock;
-use std::collections::VecDeque;
+use std::collections::{HashMap, VecDeque};
 use std::future::Future;
 use std::net::{SocketAddr, ToSocketAddrs};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
-use tokio::sync::{watch, RwLock};
+use tokio::sync::{mpsc, watch, Mutex};
 
-use crate::{Bullet, GameState, Idx, Input, Player, Tank, Time};
+use crate::protocol::{self, ClientMessage, ServerMessage, MAX_BASELINE_AGE, PROTOCOL_VERSION};
+use crate::{
+    flag_value, BinAngle, Bullet, GameEvent, GameRules, GameState, Idx, Input, Level, Map, Player,
+    Scoreboard, SimulationConfig, Snapshot, Tank, Vector2D, Wall, Weapon, GM_ONE_PIXEL,
+};
 
 use tokio_tungstenite::tungstenite;
 
-use rmp_serde;
-
 #[cfg(feature = "pathfinder_backend")]
 mod pathfinder;
 #[cfg(feature = "pathfinder_backend")]
@@ -37,9 +41,16 @@ pub fn run_client<EL: EventLoop>(host: Option<&str>) {
     let addr = host
         .and_then(|x| (x, 8999).to_socket_addrs().ok().and_then(|mut x| x.next()))
         .unwrap_or(([127, 0, 0, 1], 8999).into());
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    // how far in the past other tanks are drawn, more hides more jitter but shows them later
+    let interp_delay = flag_value::<u64>(&args, "--interp-delay").unwrap_or(100);
     let rt = tokio::runtime::Runtime::new().unwrap();
     let (input_send, input_recv) = watch::channel(Input::default());
-    let (client_loop, recv_state) = rt.block_on(client_loop(addr, input_recv));
+    let (client_loop, recv_state) = rt.block_on(client_loop(
+        addr,
+        input_recv,
+        Duration::from_millis(interp_delay),
+    ));
     let event_loop = EL::create();
     rt.spawn(client_loop);
     //rt.spawn_blocking(|| render_loop(make_renderer(), recv_state));
@@ -64,8 +75,14 @@ pub trait EventLoop {
 }
 
 pub trait Renderer {
-    fn draw_tank(&mut self, tank: &Tank);
+    fn draw_wall(&mut self, wall: &Wall);
+    /// Draws a tank with its hull in `colour`, see [`team_colour`]
+    ///
+    /// `size` is half the width of the hull in pixels, its barrel is twice that long so it ends
+    /// where bullets are fired from.
+    fn draw_tank(&mut self, tank: &Tank, size: f32, colour: (u8, u8, u8));
     fn draw_bullet(&mut self, bullet: &Bullet);
+    fn draw_scoreboard(&mut self, scoreboard: &Scoreboard);
     fn present_frame(&mut self);
 }
 
@@ -97,12 +114,18 @@ impl EventLoop for NoopRenderer {
 }
 
 impl Renderer for NoopRenderer {
-    fn draw_tank(&mut self, _tank: &Tank) {}
+    fn draw_wall(&mut self, _wall: &Wall) {}
+    fn draw_tank(&mut self, _tank: &Tank, _size: f32, _colour: (u8, u8, u8)) {}
     fn draw_bullet(&mut self, _bullet: &Bullet) {}
+    fn draw_scoreboard(&mut self, _scoreboard: &Scoreboard) {}
     fn present_frame(&mut self) {}
 }
 
-pub fn render_loop<R: Renderer>(mut renderer: R, mut recv_state: watch::Receiver<GameState>) {
+pub fn render_loop<R: Renderer>(
+    mut renderer: R,
+    mut recv_state: watch::Receiver<GameState>,
+    show_scoreboard: Arc<AtomicBool>,
+) {
     let mut state = recv_state.borrow().clone();
     loop {
         if let Some(res) = recv_state.changed().now_or_never() {
@@ -113,6 +136,9 @@ pub fn render_loop<R: Renderer>(mut renderer: R, mut recv_state: watch::Receiver
         }
 
         draw_state(&state, &mut renderer);
+        if show_scoreboard.load(Ordering::Relaxed) {
+            renderer.draw_scoreboard(&state.scoreboard());
+        }
         renderer.present_frame();
     }
     println!("Render loop ended");
@@ -122,6 +148,7 @@ pub fn render_loop<R: Renderer>(mut renderer: R, mut recv_state: watch::Receiver
 pub fn render_frame<R: Renderer>(
     renderer: &mut R,
     recv_state: &mut watch::Receiver<GameState>,
+    show_scoreboard: bool,
 ) -> Result<(), ()> {
     if let Some(res) = recv_state.changed().now_or_never() {
         if let Err(_) = res {
@@ -131,48 +158,327 @@ pub fn render_frame<R: Renderer>(
     let state = recv_state.borrow().clone();
     // Get current state
     draw_state(&state, renderer);
+    if show_scoreboard {
+        renderer.draw_scoreboard(&state.scoreboard());
+    }
     renderer.present_frame();
     Ok(())
 }
 
-//struct Client {
-//    server_state: GameState,
-//    client_state: GameState,
-//    input_history: VecDeque<(Time, Input)>,
-//}
+/// Our own tank run ahead of the server, so it answers input straight away
+///
+/// Every state from the server is rewound to, then the inputs the server hadn't applied yet are
+/// replayed on top of it.
+struct Prediction {
+    player: Idx<'static, Player>,
+    /// Inputs sent to the server that it hadn't applied in the last state it sent, oldest first
+    pending: VecDeque<Input>,
+    /// The last state from the server with every pending input replayed on top
+    state: GameState,
+    /// How far our tank is drawn from where it's predicted to be, what's left of past corrections
+    offset: Correction,
+}
+
+#[derive(Copy, Clone, Debug, Default)]
+struct Correction {
+    position: Vector2D,
+    angle: BinAngle,
+    turret_angle: BinAngle,
+}
+
+impl Correction {
+    /// Milliseconds it takes a correction to shrink to half
+    const HALF_LIFE: f64 = 50.0;
+    /// The correction a tick later
+    fn decay(self, config: &SimulationConfig) -> Self {
+        let tick = 1000.0 / config.updates_per_second as f64;
+        // out of 256, how much is still left
+        let keep = (0.5f64.powf(tick / Self::HALF_LIFE) * 256.0).round() as i64;
+        let shrink = |angle: BinAngle| BinAngle((angle.0 as i16 as i64 * keep / 256) as u16);
+        Self {
+            position: self.position * keep / 256,
+            angle: shrink(self.angle),
+            turret_angle: shrink(self.turret_angle),
+        }
+    }
+}
+
+impl Prediction {
+    fn new(player: Idx<'static, Player>, state: GameState) -> Self {
+        Self {
+            player,
+            pending: VecDeque::new(),
+            state,
+            offset: Correction::default(),
+        }
+    }
+    fn tank(&self) -> Option<(Idx<'static, Tank>, &Tank)> {
+        let idx = self.state.tank_of(self.player)?;
+        self.state.tanks.get(idx).map(|tank| (idx, tank))
+    }
+    /// Runs a tick ahead with an input that was just sent
+    fn push(&mut self, input: Input) {
+        self.pending.push_back(input.clone());
+        // never get more than a second ahead of the server
+        while self.pending.len() > self.state.config.updates_per_second as usize {
+            self.pending.pop_front();
+        }
+        if let Some(player) = self.state.players.get_mut(self.player) {
+            player.input = input;
+        }
+        self.state.advance();
+        self.offset = self.offset.decay(&self.state.config);
+    }
+    /// Rewinds to `state` from the server and replays the inputs it hadn't applied yet
+    fn reconcile(&mut self, state: GameState) {
+        if let Some(applied) = state.players[self.player].as_ref().map(|p| p.input.seq) {
+            while self
+                .pending
+                .front()
+                .map(|input| input.seq <= applied)
+                .unwrap_or(false)
+            {
+                self.pending.pop_front();
+            }
+        }
+        let before = self.tank().map(|(idx, tank)| (idx, tank.clone()));
+        self.state = state;
+        for input in &self.pending {
+            if let Some(player) = self.state.players.get_mut(self.player) {
+                player.input = input.clone();
+            }
+            self.state.advance();
+        }
+        // keep drawing our tank where it was and ease it over to where it should be, unless it
+        // was a different tank or it's so far off that sliding over would look worse
+        let limit = 4 * self.state.config.tank_size * GM_ONE_PIXEL;
+        self.offset = match (before, self.tank()) {
+            (Some((old_idx, old)), Some((idx, new))) if old_idx == idx => {
+                let offset = Correction {
+                    position: self.offset.position + (old.position - new.position),
+                    angle: self.offset.angle + (old.angle - new.angle),
+                    turret_angle: self.offset.turret_angle + (old.turret_angle - new.turret_angle),
+                };
+                match offset.position.square_length() > limit * limit {
+                    true => Correction::default(),
+                    false => offset,
+                }
+            }
+            _ => Correction::default(),
+        };
+    }
+    /// The predicted state with our tank where it should be drawn
+    fn display(&self) -> GameState {
+        let mut state = self.state.clone();
+        if let Some(idx) = state.tank_of(self.player) {
+            if let Some(tank) = state.tanks[idx].as_mut() {
+                tank.position += self.offset.position;
+                tank.angle = tank.angle + self.offset.angle;
+                tank.turret_angle = tank.turret_angle + self.offset.turret_angle;
+            }
+        }
+        state
+    }
+}
+
+/// Recent states from the server, to draw everything but our own tank a little in the past
+///
+/// States arrive unevenly, so other tanks and bullets are drawn a fixed delay behind the newest
+/// tick we could have by now, moving smoothly between the states either side of that time.
+struct Interpolation {
+    /// States from the server, oldest first
+    states: VecDeque<GameState>,
+    /// Where our clock starts
+    start: Instant,
+    /// Server tick at `start`, going by the quickest any state has taken to get here
+    offset: Option<f64>,
+    /// When the last state got here
+    last_arrival: Instant,
+    ticks_per_second: f64,
+    /// In ticks
+    delay: f64,
+}
+
+impl Interpolation {
+    /// Milliseconds a second the clock slips back, so it follows the latency when it goes up
+    const RELAX: f64 = 2.0;
+    fn new(delay: Duration, config: &SimulationConfig) -> Self {
+        let ticks_per_second = config.updates_per_second as f64;
+        let start = Instant::now();
+        Self {
+            states: VecDeque::new(),
+            start,
+            offset: None,
+            last_arrival: start,
+            ticks_per_second,
+            delay: delay.as_secs_f64() * ticks_per_second,
+        }
+    }
+    fn ticks_since_start(&self, now: Instant) -> f64 {
+        now.duration_since(self.start).as_secs_f64() * self.ticks_per_second
+    }
+    /// Adds a state that arrived at `now`
+    fn push(&mut self, state: GameState, now: Instant) {
+        if self.states.back().map(|last| last.time.0 >= state.time.0) == Some(true) {
+            // the server started over, so does our clock
+            self.states.clear();
+            self.offset = None;
+        }
+        let offset = state.time.0 as f64 - self.ticks_since_start(now);
+        let slip = (self.ticks_since_start(now) - self.ticks_since_start(self.last_arrival))
+            * Self::RELAX
+            / 1000.0;
+        self.offset = Some(match self.offset {
+            Some(old) => offset.max(old - slip),
+            None => offset,
+        });
+        self.last_arrival = now;
+        self.states.push_back(state);
+    }
+    /// Everything as it was `delay` ago, between the two states either side of then
+    fn sample(&mut self, now: Instant) -> Option<GameState> {
+        let tick = self.offset? + self.ticks_since_start(now) - self.delay;
+        while self.states.len() > 1 && self.states[1].time.0 as f64 <= tick {
+            self.states.pop_front();
+        }
+        let from = self.states.front()?;
+        let to = match self.states.get(1) {
+            Some(to) => to,
+            // nothing newer yet, wait where we are rather than guess
+            None => return Some(from.clone()),
+        };
+        let fraction = (tick - from.time.0 as f64) / (to.time.0 - from.time.0) as f64;
+        Some(interpolate(
+            from,
+            to,
+            (fraction.max(0.0).min(1.0) * 256.0) as i64,
+        ))
+    }
+    /// What to draw at `now`, other tanks and bullets in the past and ours predicted
+    fn frame(&mut self, now: Instant, prediction: &Prediction) -> GameState {
+        let predicted = prediction.display();
+        let mut state = match self.sample(now) {
+            Some(state) => state,
+            None => return predicted,
+        };
+        let player = prediction.player;
+        if let Some(idx) = state.tank_of(player) {
+            state.tanks.remove(&idx);
+        }
+        if let Some(idx) = predicted.tank_of(player) {
+            if let Some(tank) = predicted.tanks[idx].clone() {
+                state.tanks.insert(idx, tank);
+            }
+        }
+        // our bullets leave from our predicted tank, so they are predicted too
+        let ours = predicted
+            .bullets
+            .list
+            .into_iter()
+            .filter(|bullet| bullet.player == player);
+        state.bullets = std::mem::take(&mut state.bullets.list)
+            .into_iter()
+            .filter(|bullet| bullet.player != player)
+            .chain(ours)
+            .collect::<Vec<_>>()
+            .into();
+        state
+    }
+}
+
+/// `from` with its tanks `fraction` out of 256 of the way to where they are in `to`, and its
+/// bullets that far along their path
+fn interpolate(from: &GameState, to: &GameState, fraction: i64) -> GameState {
+    let lerp_angle = |from: BinAngle, to: BinAngle| {
+        from + BinAngle(((to - from).0 as i16 as i64 * fraction / 256) as u16)
+    };
+    let mut state = from.clone();
+    for (idx, tank) in &to.tanks {
+        let theirs = match tank {
+            Some(theirs) => theirs,
+            None => continue,
+        };
+        match state.tanks.get_mut(idx) {
+            Some(ours) => {
+                ours.position += (theirs.position - ours.position) * fraction / 256;
+                ours.angle = lerp_angle(ours.angle, theirs.angle);
+                ours.turret_angle = lerp_angle(ours.turret_angle, theirs.turret_angle);
+            }
+            // spawned since, maybe into the slot of a tank that died, there's nothing to move from
+            None => state.tanks.insert(idx, theirs.clone()),
+        }
+    }
+    let config = state.config.clone();
+    for bullet in &mut state.bullets.list {
+        bullet.position += bullet.velocity(&config) * fraction / 256;
+    }
+    state
+}
 
 async fn client_loop(
     addr: SocketAddr,
-    mut input_ui_recv: watch::Receiver<Input>,
+    input_ui_recv: watch::Receiver<Input>,
+    interp_delay: Duration,
 ) -> (impl Future<Output = ()> + Send, watch::Receiver<GameState>) {
     let (socket, _) = tokio_tungstenite::connect_async(format!("ws://{}/stream", addr))
         .await
         .unwrap();
     let (mut sink, mut stream) = socket.split();
-    let player_id = parse_id(stream.next().await.unwrap().unwrap()).unwrap();
-    let init_game_state = parse_state(stream.next().await.unwrap().unwrap()).unwrap();
+    let name = std::env::var("USER").unwrap_or_else(|_| "player".to_owned());
+    sink.send(tungstenite::Message::Binary(protocol::encode(
+        &ClientMessage::hello(name),
+    )))
+    .await
+    .unwrap();
+    let (player_id, map, config, rules) = parse_welcome(stream.next().await.unwrap().unwrap());
+    let level = Arc::new(Level::from(&map));
+    let config = Arc::new(config);
+    let (_, snapshot) = parse_state(stream.next().await.unwrap().unwrap()).unwrap();
+    sink.send(tungstenite::Message::Binary(protocol::encode(
+        &ClientMessage::Ack(snapshot.time),
+    )))
+    .await
+    .unwrap();
+    let init_game_state = GameState::from_snapshot(
+        snapshot.clone(),
+        level.clone(),
+        config.clone(),
+        rules.clone(),
+    );
+    // snapshots we've acknowledged, the server sends changes against one of them
+    let mut baselines = VecDeque::from(vec![snapshot]);
+    let sink = Mutex::new(sink);
+    let prediction = Mutex::new(Prediction::new(player_id, init_game_state.clone()));
+    let mut interpolation = Interpolation::new(interp_delay, &config);
+    interpolation.push(init_game_state.clone(), Instant::now());
+    let interpolation = Mutex::new(interpolation);
     let (send_state, recv_state) = watch::channel(init_game_state);
-    let (input_send, input_recv) = watch::channel(Default::default());
+    // every input goes out on its own, the server applies them a tick each like we do
+    let (input_send, mut input_recv) = mpsc::unbounded_channel();
     (
         async move {
-            let (history_write, history_read) = BiLock::new(VecDeque::<Input>::new());
             let input_loop = async {
                 // need async type ascription to remove this
                 if false {
                     return Ok::<(), ()>(());
                 }
-                let mut input_seq = 0;
+                // one input every tick, each one is a tick of prediction
+                let mut interval = tokio::time::interval(config.tick_duration());
+                // the server starts everyone with a default input numbered 0
+                let mut input_seq = 1;
                 loop {
-                    let sleep = tokio::time::sleep(Duration::from_secs(1) / 60);
-                    input_ui_recv.changed().await;
+                    interval.tick().await;
                     let mut input = input_ui_recv.borrow().clone();
                     input.seq = input_seq;
                     input_seq += 1;
-                    input_send.send(input).map_err(|_|())?;
-                    input_history.write().await.push_back(input);
-
-                    // limit speed
-                    sleep.await;
+                    input_send.send(input.clone()).map_err(|_| ())?;
+                    let mut prediction = prediction.lock().await;
+                    prediction.push(input);
+                    let frame = interpolation
+                        .lock()
+                        .await
+                        .frame(Instant::now(), &prediction);
+                    send_state.send(frame).map_err(|_| ())?;
                 }
             };
             let input_send = async {
@@ -180,80 +486,335 @@ async fn client_loop(
                 if false {
                     return Ok::<(), ()>(());
                 }
-                loop {
-                    input_recv.changed().await;
-                    let mut input = input_recv.borrow().clone();
-                    sink.send(tungstenite::Message::Binary(
-                        rmp_serde::to_vec(&input).map_err(|_| ())?,
-                    ))
-                    .await
-                    .map_err(|_| ())?;
+                while let Some(input) = input_recv.recv().await {
+                    sink.lock()
+                        .await
+                        .send(tungstenite::Message::Binary(protocol::encode(
+                            &ClientMessage::Input(input),
+                        )))
+                        .await
+                        .map_err(|_| ())?;
                 }
+                Err(())
             };
             let recv_loop = async {
                 // need async block type ascription to remove this
                 if false {
                     return Ok::<(), _>(());
                 }
+                let mut last_state: Option<GameState> = None;
+                let mut kill_feed = KillFeed::default();
+                kill_feed.update(&prediction.lock().await.state);
                 loop {
-                    let mut msg = stream.next().await.unwrap().map_err(|_| ())?;
+                    let mut messages = vec![stream.next().await.unwrap().map_err(|_| ())?];
                     // Attempt to drain any states that may be buffered
                     while let Some(next_msg) = stream.next().now_or_never() {
-                        msg = next_msg.unwrap().unwrap()
+                        messages.push(next_msg.unwrap().map_err(|_| ())?);
                     }
-                    let state = parse_state(msg).unwrap();
-                }
-            };
-            let predict_loop = async {
-                    let game_seq = match state.players[player_id].as_ref() {
+                    // only the newest state is kept, but every event counts
+                    let mut latest = None;
+                    for msg in messages {
+                        match protocol::decode(&msg.into_data()) {
+                            Some(ServerMessage::State { hash, state }) => {
+                                latest = Some((hash, state.into_owned()))
+                            }
+                            Some(ServerMessage::Delta { hash, delta }) => {
+                                // the server won't build on anything older again
+                                baselines.retain(|b| b.time.0 >= delta.baseline.0);
+                                match baselines.front().and_then(|b| b.apply_delta(&delta)) {
+                                    Some(snapshot) => latest = Some((hash, snapshot)),
+                                    None => {
+                                        println!("NO BASELINE AT {:?} FOR DELTA", delta.baseline)
+                                    }
+                                }
+                            }
+                            Some(ServerMessage::Events { events, .. }) => {
+                                for event in &events {
+                                    if let Some(line) = kill_feed.line(event) {
+                                        println!("{}", line);
+                                    }
+                                }
+                            }
+                            _ => {}
+                        }
+                    }
+                    let (hash, snapshot) = match latest {
+                        Some(latest) => latest,
                         None => continue,
-                        Some(p) => p.input.seq,
                     };
-                    let mut input_history = input_history.write().await;
-                    // remove inputs already included
-                    while input_history
-                        .front()
-                        .map(|i| i.seq <= game_seq)
-                        .unwrap_or(false)
-                    {
-                        input_history.pop_front();
+                    sink.lock()
+                        .await
+                        .send(tungstenite::Message::Binary(protocol::encode(
+                            &ClientMessage::Ack(snapshot.time),
+                        )))
+                        .await
+                        .map_err(|_| ())?;
+                    let time = snapshot.time.0;
+                    baselines.push_back(snapshot.clone());
+                    let max_age = config.ticks(MAX_BASELINE_AGE) as u64;
+                    baselines.retain(|b| b.time.0 + max_age >= time);
+                    // replaying a tick only works when we were sent everything, both times
+                    let whole = !snapshot.culled;
+                    let state = GameState::from_snapshot(
+                        snapshot,
+                        level.clone(),
+                        config.clone(),
+                        rules.clone(),
+                    );
+                    kill_feed.update(&state);
+                    check_desync(last_state.take().filter(|_| whole).as_ref(), hash, &state);
+                    if whole {
+                        last_state = Some(state.clone());
                     }
-                    let input_history = input_history.downgrade();
-                    println!("Predicted Frames: {:?}", input_history.len());
-                    // broadcast state
-                    let predicted_state =
-                        input_history
-                            .iter()
-                            .fold(state.clone(), |mut state, input| {
-                                state.players[player_id].as_mut().unwrap().input = input.clone();
-                                state.tick()
-                            });
-                    send_state.send(predicted_state).unwrap();
-            }
-            let _ = try_join!(input_loop, recv_loop);
+                    interpolation
+                        .lock()
+                        .await
+                        .push(state.clone(), Instant::now());
+                    // drawn on our own clock in the input loop, not when states happen to arrive
+                    prediction.lock().await.reconcile(state);
+                }
+            };
+            let _ = try_join!(input_loop, input_send, recv_loop);
         },
         recv_state,
     )
 }
 
-fn parse_id(msg: tungstenite::Message) -> Option<Idx<'static, Player>> {
-    rmp_serde::from_read_ref(&msg.into_data()).ok()
+/// Names of everyone we've seen in a state or heard join, for the kill feed
+///
+/// Events come ahead of the state they happened in, so players who just joined aren't in any
+/// state yet and players who just left still are.
+#[derive(Default)]
+struct KillFeed {
+    names: HashMap<Idx<'static, Player>, String>,
+}
+
+impl KillFeed {
+    fn update(&mut self, state: &GameState) {
+        for (idx, player) in &state.players {
+            if let Some(player) = player {
+                self.names.insert(idx, player.name.clone());
+            }
+        }
+    }
+    /// A line for the kill feed, for the events that go in one
+    fn line(&mut self, event: &GameEvent) -> Option<String> {
+        if let GameEvent::PlayerJoined { player, name } = event {
+            self.names.insert(*player, name.clone());
+        }
+        let line = self.describe(event);
+        if let GameEvent::PlayerLeft { player } = event {
+            self.names.remove(player);
+        }
+        line
+    }
+    fn describe(&self, event: &GameEvent) -> Option<String> {
+        let name = |player| {
+            self.names
+                .get(&player)
+                .map(String::as_str)
+                .unwrap_or("<gone>")
+        };
+        match event {
+            GameEvent::TankKilled { killer, victim, .. } if killer == victim => {
+                Some(format!("{} destroyed their own tank", name(*victim)))
+            }
+            GameEvent::TankKilled { killer, victim, .. } => Some(format!(
+                "{} destroyed {}'s tank",
+                name(*killer),
+                name(*victim)
+            )),
+            GameEvent::PlayerJoined { name, .. } => Some(format!("{} joined", name)),
+            GameEvent::PlayerLeft { player } => Some(format!("{} left", name(*player))),
+            _ => None,
+        }
+    }
+}
+
+/// Our player index and the match setup, panics with the server's reason if it turned us away
+fn parse_welcome(
+    msg: tungstenite::Message,
+) -> (Idx<'static, Player>, Map, SimulationConfig, GameRules) {
+    match protocol::decode(&msg.into_data()) {
+        Some(ServerMessage::Welcome {
+            player,
+            map,
+            config,
+            rules,
+        }) => {
+            if let Err(reason) = config.validate() {
+                panic!("Server sent a config we can't run: {}", reason);
+            }
+            (player, map, config, rules)
+        }
+        Some(ServerMessage::Rejected { version, reason }) => panic!(
+            "Server at protocol version {} rejected us at version {}: {}",
+            version, PROTOCOL_VERSION, reason
+        ),
+        _ => panic!("Server didn't answer with a welcome"),
+    }
+}
+/// A whole state from the server along with the server's hash of it
+fn parse_state(msg: tungstenite::Message) -> Option<(u64, Snapshot)> {
+    match protocol::decode(&msg.into_data())? {
+        ServerMessage::State { hash, state } => Some((hash, state.into_owned())),
+        _ => None,
+    }
+}
+
+/// Logs a desync if `state` doesn't match the hash the server sent with it, or if replaying the
+/// tick from the previous state with the same inputs gives a different result
+fn check_desync(last: Option<&GameState>, hash: u64, state: &GameState) {
+    let received = state.content_hash();
+    if received != hash {
+        println!(
+            "DESYNC AT {:?}: RECEIVED STATE HASHES TO {:x}, SERVER SENT {:x}",
+            state.time, received, hash
+        );
+        return;
+    }
+    let last = match last {
+        Some(last) if last.time.0.wrapping_add(1) == state.time.0 => last,
+        _ => return,
+    };
+    // joins and leaves happen between ticks, we can't replay those
+    let same_players = last.players.len() == state.players.len()
+        && (&last.players)
+            .into_iter()
+            .zip(&state.players)
+            .all(|((_, a), (_, b))| a.is_some() == b.is_some());
+    if !same_players {
+        return;
+    }
+    let mut replay = last.clone();
+    for (idx, player) in &state.players {
+        if let (Some(ours), Some(theirs)) = (replay.players.get_mut(idx), player) {
+            ours.input = theirs.input.clone();
+        }
+    }
+    let (replay, _) = replay.tick();
+    if replay.content_hash() != hash {
+        println!(
+            "DESYNC AT {:?}: FIRST DIFFERENCE IN {:?}",
+            state.time,
+            replay.first_difference(state)
+        );
+    }
 }
-fn parse_state(msg: tungstenite::Message) -> Option<GameState> {
-    rmp_serde::from_read_ref(&msg.into_data()).ok()
+
+/// Scoreboard as lines of text, starting with a header
+pub fn scoreboard_lines(scoreboard: &Scoreboard) -> Vec<String> {
+    let header = format!(
+        "{:<16} {:>5} {:>6} {:>7} {:>5}",
+        "PLAYER", "KILLS", "DEATHS", "DAMAGE", "ACC"
+    );
+    std::iter::once(header)
+        .chain(scoreboard.rows.iter().map(|row| {
+            format!(
+                "{:<16} {:>5} {:>6} {:>7} {:>4.0}%",
+                row.name,
+                row.stats.kills,
+                row.stats.deaths,
+                row.stats.damage_dealt,
+                row.stats.accuracy() * 100.0
+            )
+        }))
+        .collect()
 }
 
-fn get_input(seq: u64) -> Input {
-    todo!();
+/// Length in pixels and rgb colour to draw a bullet with
+pub fn bullet_style(bullet: &Bullet) -> (f32, (u8, u8, u8)) {
+    match bullet.kind {
+        Weapon::Cannon => (20.0, (255, 0, 0)),
+        Weapon::MachineGun => (10.0, (255, 200, 0)),
+        Weapon::Shotgun => (8.0, (255, 120, 0)),
+        Weapon::HeavyShell => (32.0, (120, 0, 160)),
+    }
+}
+
+/// Rgb colour for a tank's hull, green in free for all
+pub fn team_colour(team: Option<u8>) -> (u8, u8, u8) {
+    match team {
+        None => (0, 255, 0),
+        Some(0) => (220, 50, 50),
+        Some(1) => (50, 110, 255),
+        Some(2) => (240, 200, 0),
+        Some(_) => (200, 60, 220),
+    }
+}
+
+/// `colour` darkened a little, for turrets
+pub fn shade(colour: (u8, u8, u8)) -> (u8, u8, u8) {
+    let darken = |c: u8| (c as u16 * 4 / 5) as u8;
+    (darken(colour.0), darken(colour.1), darken(colour.2))
 }
 
 fn draw_state(state: &GameState, r: &mut impl Renderer) {
+    for (_i, wall) in &state.level.walls {
+        r.draw_wall(wall)
+    }
+    let size = state.config.tank_size as f32;
     for (_i, tank) in &state.tanks {
         if let Some(tank) = tank {
-            r.draw_tank(tank)
+            r.draw_tank(tank, size, team_colour(tank.team))
         }
     }
     for (_i, bullet) in &state.bullets {
         r.draw_bullet(bullet)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn arena() -> GameState {
+        let map = Map::empty("client", euclid::Size2D::new(800, 800));
+        GameState::with_level(
+            Arc::new(Level::from(&map)),
+            Default::default(),
+            Default::default(),
+        )
+    }
+
+    #[test]
+    fn interpolation_takes_tanks_in_reused_slots_from_the_newer_state() {
+        let mut state = arena();
+        let old = state.add_player("old".to_owned());
+        state = state.tick().0;
+        let from = state.clone();
+        state.remove_player(&old);
+        let new = state.add_player("new".to_owned());
+        let to = state.tick().0;
+        let (old_tank, new_tank) = (from.tank_of(old).unwrap(), to.tank_of(new).unwrap());
+        // the same slot in a new generation
+        assert!(old_tank.0 == new_tank.0 && old_tank != new_tank);
+        let between = interpolate(&from, &to, 128);
+        assert_eq!(between.tanks[new_tank], to.tanks[new_tank]);
+        assert!(between.tanks[old_tank].is_none());
+    }
+
+    #[test]
+    fn kill_feed_names_players_who_just_joined() {
+        let mut state = arena();
+        let mut feed = KillFeed::default();
+        feed.update(&state);
+        // the events get here before any state with the new player in it
+        let player = state.add_player("newcomer".to_owned());
+        let lines: Vec<_> = state
+            .advance()
+            .iter()
+            .filter_map(|event| feed.line(event))
+            .collect();
+        assert_eq!(lines, vec!["newcomer joined"]);
+        let killed = GameEvent::TankKilled {
+            killer: player,
+            victim: player,
+            position: crate::Point2D::zero(),
+        };
+        assert_eq!(
+            feed.line(&killed).as_deref(),
+            Some("newcomer destroyed their own tank")
+        );
+    }
+}
@@ -2,8 +2,9 @@ use std::collections::HashSet;
 use std::f32::consts::TAU;
 use std::mem;
 
+use crate::client::scoreboard_lines;
 use crate::client::{EventLoop, Renderer};
-use crate::{Bullet, Drive, Input, Tank, Turn};
+use crate::{Bullet, Drive, Input, Scoreboard, Tank, Turn, Wall, Weapon};
 
 use tokio::sync::watch;
 
@@ -28,6 +29,7 @@ impl EventLoop for PathfinderEventLoop {
     fn run_loop(self, send_input: watch::Sender<Input>) {
         use glutin::event::{ElementState, Event, KeyboardInput, VirtualKeyCode, WindowEvent};
         let mut keymap = HashSet::new();
+        let mut weapon = Weapon::default();
         self.inner.run(move |e, _, control_flow| {
             match e {
                 Event::WindowEvent {
@@ -68,12 +70,24 @@ impl EventLoop for PathfinderEventLoop {
                         _ => None,
                     };
                     let fire = keymap.contains(&VirtualKeyCode::Space);
+                    let weapon_keys = [
+                        VirtualKeyCode::Key1,
+                        VirtualKeyCode::Key2,
+                        VirtualKeyCode::Key3,
+                        VirtualKeyCode::Key4,
+                    ];
+                    for (key, selected) in weapon_keys.iter().zip(&Weapon::ALL) {
+                        if keymap.contains(key) {
+                            weapon = *selected;
+                        }
+                    }
                     send_input
                         .send(Input {
                             drive,
                             rotate,
                             turret,
                             fire,
+                            weapon,
                             seq: 0,
                         })
                         .unwrap();
@@ -142,12 +156,22 @@ impl PathfinderRenderer {
 }
 
 impl Renderer for PathfinderRenderer {
-    fn draw_tank(&mut self, tank: &Tank) {
+    fn draw_wall(&mut self, wall: &Wall) {
+        let size = wall.half_size.to_f32() * 2.0;
+        let rect = RectF::new(
+            Vector2F::new(wall.center.x as f32, wall.center.y as f32)
+                - Vector2F::new(size.width, size.height) * 0.5,
+            Vector2F::new(size.width, size.height),
+        );
+        self.context.set_fill_style(ColorU::new(128, 128, 128, 255));
+        self.context.fill_rect(rect);
+    }
+    fn draw_tank(&mut self, tank: &Tank, _size: f32, (r, g, b): (u8, u8, u8)) {
         let rect = RectF::new(
             Vector2F::new(tank.position.0, tank.position.1),
             Vector2F::new(2.0, 2.0),
         );
-        self.context.set_fill_style(ColorU::new(0, 255, 0, 255));
+        self.context.set_fill_style(ColorU::new(r, g, b, 255));
         self.context.fill_rect(rect);
     }
     fn draw_bullet(&mut self, bullet: &Bullet) {
@@ -155,13 +179,20 @@ impl Renderer for PathfinderRenderer {
         path.ellipse(
             Vector2F::new(bullet.position.0, bullet.position.1),
             Vector2F::new(1.0, 0.5),
-            bullet.angle,
+            bullet.angle.to_angle().radians,
             0.0,
             TAU,
         );
         self.context.set_fill_style(ColorU::new(255, 0, 0, 255));
         self.context.fill_path(path, FillRule::Winding);
     }
+    fn draw_scoreboard(&mut self, scoreboard: &Scoreboard) {
+        self.context.set_fill_style(ColorU::new(255, 255, 255, 255));
+        for (i, line) in scoreboard_lines(scoreboard).iter().enumerate() {
+            self.context
+                .fill_text(line, Vector2F::new(56.0, 70.0 + 24.0 * i as f32));
+        }
+    }
     fn present_frame(&mut self) {
         let size = self.context.canvas().size().to_f32();
         let font_bruh = CanvasFontContext::from_system_source();
@@ -1,7 +1,9 @@
 use std::f32::consts::TAU;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
 
 use super::{render_loop, EventLoop, RaqoteRenderer, Renderer};
-use crate::{Bullet, Drive, GameState, Input, Tank, Turn};
+use crate::{Bullet, Drive, GameState, Input, Scoreboard, Tank, Turn, Wall, Weapon};
 
 use tokio::sync::watch;
 
@@ -52,8 +54,11 @@ impl EventLoop for PixelsEventLoop {
                 window,
             }
         };
-        rt.spawn_blocking(|| render_loop(make_renderer(), recv_state));
+        let show_scoreboard = Arc::new(AtomicBool::new(false));
+        let render_show_scoreboard = show_scoreboard.clone();
+        rt.spawn_blocking(|| render_loop(make_renderer(), recv_state, render_show_scoreboard));
         let mut input = WinitInputHelper::new();
+        let mut weapon = Weapon::default();
         self.event_loop.run(move |event, _, control_flow| {
             println!("{:?}", event);
             if input.update(&event) {
@@ -82,11 +87,24 @@ impl EventLoop for PixelsEventLoop {
                     _ => None,
                 };
                 let fire = input.key_held(VirtualKeyCode::Space);
+                let weapon_keys = [
+                    VirtualKeyCode::Key1,
+                    VirtualKeyCode::Key2,
+                    VirtualKeyCode::Key3,
+                    VirtualKeyCode::Key4,
+                ];
+                for (key, selected) in weapon_keys.iter().zip(&Weapon::ALL) {
+                    if input.key_pressed(*key) {
+                        weapon = *selected;
+                    }
+                }
+                show_scoreboard.store(input.key_held(VirtualKeyCode::Tab), Ordering::Relaxed);
                 if let Err(_) = send_input.send(Input {
                     drive,
                     rotate,
                     turret,
                     fire,
+                    weapon,
                     seq: 0,
                 }) {
                     *control_flow = ControlFlow::Exit;
@@ -143,12 +161,18 @@ impl EventLoop for PixelsEventLoop {
 }
 
 impl Renderer for PixelsRenderer {
-    fn draw_tank(&mut self, tank: &Tank) {
-        self.raqote.draw_tank(tank);
+    fn draw_wall(&mut self, wall: &Wall) {
+        self.raqote.draw_wall(wall);
+    }
+    fn draw_tank(&mut self, tank: &Tank, size: f32, colour: (u8, u8, u8)) {
+        self.raqote.draw_tank(tank, size, colour);
     }
     fn draw_bullet(&mut self, bullet: &Bullet) {
         self.raqote.draw_bullet(bullet);
     }
+    fn draw_scoreboard(&mut self, scoreboard: &Scoreboard) {
+        self.raqote.draw_scoreboard(scoreboard);
+    }
     fn present_frame(&mut self) {
         let frame = self.pixels.get_frame();
         let pixels = self.raqote.get_data_u8();
@@ -1,7 +1,7 @@
 use std::f32::consts::TAU;
 
-use crate::client::{EventLoop, Renderer};
-use crate::{Bullet, Drive, Gm, Input, Tank, Turn, GM_SCALE};
+use crate::client::{bullet_style, scoreboard_lines, shade, EventLoop, Renderer};
+use crate::{Bullet, Drive, Gm, Input, Scoreboard, Tank, Turn, Wall, GM_SCALE};
 
 use euclid::{Box2D, Point2D, Transform2D, Vector2D};
 
@@ -11,8 +11,15 @@ use raqote::{
     DrawOptions, DrawTarget, Path, PathBuilder, SolidSource, Source, StrokeStyle, Transform,
 };
 
+use font_kit::family_name::FamilyName;
+use font_kit::font::Font;
+use font_kit::properties::Properties;
+use font_kit::source::SystemSource;
+
 pub struct RaqoteRenderer {
     raqote: raqote::DrawTarget,
+    /// For the scoreboard, without one it is drawn with no text
+    font: Option<Font>,
 }
 
 impl RaqoteRenderer {
@@ -21,7 +28,14 @@ impl RaqoteRenderer {
         raqote.set_transform(
             &Transform2D::create_scale(1.0, -1.0).post_translate(Vector2D::new(0.0, height as f32)),
         );
-        Self { raqote }
+        let font = SystemSource::new()
+            .select_best_match(&[FamilyName::Monospace], &Properties::new())
+            .ok()
+            .and_then(|handle| handle.load().ok());
+        if font.is_none() {
+            println!("NO MONOSPACE FONT FOUND, THE SCOREBOARD WILL HAVE NO TEXT");
+        }
+        Self { raqote, font }
     }
     pub fn get_data_u8(&self) -> &[u8] {
         self.raqote.get_data_u8()
@@ -37,7 +51,27 @@ impl RaqoteRenderer {
     }
 }
 impl Renderer for RaqoteRenderer {
-    fn draw_tank(&mut self, tank: &Tank) {
+    fn draw_wall(&mut self, wall: &Wall) {
+        let og_transform = self.raqote.get_transform().clone();
+        let half_size = (wall.half_size / GM_SCALE).to_f32();
+        self.raqote.set_transform(
+            &og_transform
+                .pre_translate((wall.center / GM_SCALE).to_vector().to_f32())
+                .pre_rotate(-wall.angle.to_angle()),
+        );
+        self.raqote.fill_rect(
+            -half_size.width,
+            -half_size.height,
+            half_size.width * 2.0,
+            half_size.height * 2.0,
+            &Source::Solid(SolidSource::from_unpremultiplied_argb(255, 128, 128, 128)),
+            &DrawOptions::default(),
+        );
+        self.raqote.set_transform(&og_transform);
+    }
+    fn draw_tank(&mut self, tank: &Tank, size: f32, colour: (u8, u8, u8)) {
+        let (r, g, b) = colour;
+        let (tr, tg, tb) = shade(colour);
         let og_transform = self.raqote.get_transform().clone();
         let translate = og_transform.pre_translate((tank.position / GM_SCALE).to_vector().to_f32());
         if !Box2D::new(
@@ -58,30 +92,30 @@ impl Renderer for RaqoteRenderer {
             return;
         }
         self.raqote
-            .set_transform(&translate.pre_rotate(-tank.angle));
+            .set_transform(&translate.pre_rotate(-tank.angle.to_angle()));
 
         self.raqote.fill_rect(
-            -20.0,
-            -20.0,
-            40.0,
-            40.0,
-            &Source::Solid(SolidSource::from_unpremultiplied_argb(255, 0, 255, 0)),
+            -size,
+            -size,
+            size * 2.0,
+            size * 2.0,
+            &Source::Solid(SolidSource::from_unpremultiplied_argb(255, r, g, b)),
             &DrawOptions::default(),
         );
         self.raqote
-            .set_transform(&translate.pre_rotate(-tank.turret_angle));
+            .set_transform(&translate.pre_rotate(-tank.turret_angle.to_angle()));
         self.raqote.fill_rect(
             0.0,
             -5.0,
-            40.0,
+            size * 2.0,
             10.0,
-            &Source::Solid(SolidSource::from_unpremultiplied_argb(255, 0, 200, 0)),
+            &Source::Solid(SolidSource::from_unpremultiplied_argb(255, tr, tg, tb)),
             &DrawOptions::default(),
         );
         self.raqote.set_transform(&translate);
         self.raqote.fill_rect(
             -30.0,
-            -45.0,
+            -size - 25.0,
             60.0,
             5.0,
             &Source::Solid(SolidSource::from_unpremultiplied_argb(255, 255, 0, 0)),
@@ -89,12 +123,20 @@ impl Renderer for RaqoteRenderer {
         );
         self.raqote.fill_rect(
             -30.0,
-            -45.0,
-            60.0 * (tank.health as f32 / 100.0),
+            -size - 25.0,
+            60.0 * tank.health_fraction(),
             5.0,
             &Source::Solid(SolidSource::from_unpremultiplied_argb(255, 0, 255, 0)),
             &DrawOptions::default(),
         );
+        self.raqote.fill_rect(
+            -30.0,
+            -size - 32.0,
+            60.0 * tank.weapon.magazine_fraction(),
+            3.0,
+            &Source::Solid(SolidSource::from_unpremultiplied_argb(255, 255, 200, 0)),
+            &DrawOptions::default(),
+        );
         self.raqote.set_transform(&og_transform);
     }
     fn draw_bullet(&mut self, bullet: &Bullet) {
@@ -106,12 +148,13 @@ impl Renderer for RaqoteRenderer {
         {
             return;
         }
+        let (length, (r, g, b)) = bullet_style(bullet);
         let og_transform = self.raqote.get_transform().clone();
         self.raqote.set_transform(
             &og_transform
                 .pre_translate((bullet.position / GM_SCALE).to_vector().to_f32())
-                .pre_rotate(-bullet.angle)
-                .pre_scale(2.0, 0.5),
+                .pre_rotate(-bullet.angle.to_angle())
+                .pre_scale(length / 10.0, length / 40.0),
         );
         let mut path = PathBuilder::new();
         path.arc(0.0, 0.0, 10.0, 0.0, TAU);
@@ -119,11 +162,42 @@ impl Renderer for RaqoteRenderer {
         let path = path.finish();
         self.raqote.fill(
             &path,
-            &Source::Solid(SolidSource::from_unpremultiplied_argb(255, 255, 0, 0)),
+            &Source::Solid(SolidSource::from_unpremultiplied_argb(255, r, g, b)),
             &DrawOptions::default(),
         );
         self.raqote.set_transform(&og_transform);
     }
+    fn draw_scoreboard(&mut self, scoreboard: &Scoreboard) {
+        let og_transform = self.raqote.get_transform().clone();
+        // text is drawn in screen space, without the y flip
+        self.raqote.set_transform(&Transform::identity());
+        self.raqote.fill_rect(
+            40.0,
+            40.0,
+            520.0,
+            40.0 + 24.0 * scoreboard.rows.len() as f32,
+            &Source::Solid(SolidSource::from_unpremultiplied_argb(200, 0, 0, 0)),
+            &DrawOptions::default(),
+        );
+        let font = match &self.font {
+            Some(font) => font,
+            None => {
+                self.raqote.set_transform(&og_transform);
+                return;
+            }
+        };
+        for (i, line) in scoreboard_lines(scoreboard).iter().enumerate() {
+            self.raqote.draw_text(
+                font,
+                18.0,
+                line,
+                Point2D::new(56.0, 70.0 + 24.0 * i as f32),
+                &Source::Solid(SolidSource::from_unpremultiplied_argb(255, 255, 255, 255)),
+                &DrawOptions::default(),
+            );
+        }
+        self.raqote.set_transform(&og_transform);
+    }
     fn present_frame(&mut self) {
         self.raqote
             .clear(SolidSource::from_unpremultiplied_argb(255, 0, 0, 0));
@@ -0,0 +1,117 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{BinAngle, Weapon, WeaponSpec, GM_ONE_PIXEL};
+
+/// Balance numbers the simulation reads every tick
+///
+/// Loaded by the server and sent to clients when they connect, so both sides simulate with the
+/// same values. Everything is in pixels, degrees and milliseconds so the file stays readable and
+/// doesn't depend on the tick rate.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SimulationConfig {
+    /// Simulation steps per second
+    pub updates_per_second: i64,
+    /// Pixels per second
+    pub tank_speed: i64,
+    /// Degrees per second, for both the hull and the turret
+    pub tank_turn_rate: i64,
+    /// Half the width of a tank's square hitbox, in pixels
+    pub tank_size: i64,
+    pub tank_health: i64,
+    pub weapons: WeaponSpecs,
+}
+
+/// A [`WeaponSpec`] for every [`Weapon`]
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct WeaponSpecs {
+    pub cannon: WeaponSpec,
+    pub machine_gun: WeaponSpec,
+    pub shotgun: WeaponSpec,
+    pub heavy_shell: WeaponSpec,
+}
+
+impl SimulationConfig {
+    /// Most ticks per second the simulation can run at, a tick has to last at least a millisecond
+    pub const MAX_UPDATES_PER_SECOND: i64 = 1000;
+    #[cfg(feature = "server")]
+    pub fn load(path: &std::path::Path) -> ron::Result<Self> {
+        ron::de::from_reader(std::fs::File::open(path)?)
+    }
+    /// Why the simulation can't run with these values, if it can't
+    pub fn validate(&self) -> Result<(), String> {
+        if self.updates_per_second <= 0 || self.updates_per_second > Self::MAX_UPDATES_PER_SECOND {
+            return Err(format!(
+                "updates_per_second has to be between 1 and {}, not {}",
+                Self::MAX_UPDATES_PER_SECOND,
+                self.updates_per_second
+            ));
+        }
+        let positive = |name: &str, value: i64| match value > 0 {
+            true => Ok(()),
+            false => Err(format!("{} has to be above 0, not {}", name, value)),
+        };
+        // anything shorter rounds down to no time at all
+        let lasts_a_tick = |name: &str, millis: u32| match self.ticks(millis) > 0 {
+            true => Ok(()),
+            false => Err(format!(
+                "{} has to last at least a tick, {}ms is less than one at {} ticks per second",
+                name, millis, self.updates_per_second
+            )),
+        };
+        positive("tank_speed", self.tank_speed)?;
+        positive("tank_size", self.tank_size)?;
+        positive("tank_health", self.tank_health)?;
+        let turn = self.tank_turn_rate * 65536 / 360 / self.updates_per_second;
+        if turn <= 0 || turn >= 65536 {
+            return Err(format!(
+                "tank_turn_rate has to turn a tank some but less than all the way around each \
+                 tick, {} degrees per second doesn't",
+                self.tank_turn_rate
+            ));
+        }
+        for &kind in Weapon::ALL.iter() {
+            let spec = self.weapon(kind);
+            let name = |field| format!("{:?} {}", kind, field);
+            positive(&name("speed"), spec.speed)?;
+            positive(&name("magazine_size"), spec.magazine_size as i64)?;
+            positive(&name("pellets"), spec.pellets as i64)?;
+            lasts_a_tick(&name("lifetime"), spec.lifetime)?;
+            lasts_a_tick(&name("cooldown"), spec.cooldown)?;
+            lasts_a_tick(&name("reload_time"), spec.reload_time)?;
+        }
+        Ok(())
+    }
+    pub fn weapon(&self, kind: Weapon) -> &WeaponSpec {
+        match kind {
+            Weapon::Cannon => &self.weapons.cannon,
+            Weapon::MachineGun => &self.weapons.machine_gun,
+            Weapon::Shotgun => &self.weapons.shotgun,
+            Weapon::HeavyShell => &self.weapons.heavy_shell,
+        }
+    }
+    /// Real time between two ticks
+    pub fn tick_duration(&self) -> std::time::Duration {
+        std::time::Duration::from_secs(1) / self.updates_per_second as u32
+    }
+    /// Number of ticks closest to `millis` milliseconds
+    pub fn ticks(&self, millis: u32) -> u32 {
+        ((millis as i64 * self.updates_per_second + 500) / 1000) as u32
+    }
+    /// Gm moved per tick at `speed` pixels per second
+    pub fn per_tick(&self, speed: i64) -> i64 {
+        speed * GM_ONE_PIXEL / self.updates_per_second
+    }
+    /// How far a tank or turret turns in a tick
+    pub fn turn_per_tick(&self) -> BinAngle {
+        BinAngle((self.tank_turn_rate * 65536 / 360 / self.updates_per_second) as u16)
+    }
+}
+
+/// The values in `config/simulation.ron`, which the server also runs with when not given a
+/// `--config`
+impl Default for SimulationConfig {
+    fn default() -> Self {
+        ron::de::from_str(include_str!("../config/simulation.ron"))
+            .expect("config/simulation.ron doesn't parse")
+    }
+}
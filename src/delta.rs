@@ -0,0 +1,132 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{Bullet, Player, Point2D, Snapshot, StableList, Tank, Time};
+
+/// What changed between a state a client already has and a newer one
+///
+/// The server builds these against the last state the client acknowledged, see
+/// [`ClientMessage::Ack`](crate::ClientMessage::Ack).
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct StateDelta {
+    /// Time of the state this applies to
+    pub baseline: Time,
+    time: Time,
+    players: SlotsDelta<Player>,
+    tanks: SlotsDelta<Tank>,
+    tank_bullets: SlotsDelta<Vec<Bullet>>,
+    bullets: Vec<BulletDelta>,
+    culled: bool,
+}
+
+/// The slots of a [`StableList`] that differ from the baseline, each sent whole
+#[derive(Clone, Debug, Serialize, Deserialize)]
+struct SlotsDelta<E> {
+    len: usize,
+    /// Index, generation and contents of every changed slot
+    changed: Vec<(usize, u32, Option<E>)>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+enum BulletDelta {
+    /// The bullet at this position in the baseline's list, moved to a new position
+    Moved(usize, Point2D),
+    /// A bullet fired since the baseline, or one that changed more than its position
+    Full(Bullet),
+}
+
+impl<E: Clone + PartialEq> SlotsDelta<E> {
+    fn between(baseline: &StableList<E>, list: &StableList<E>) -> Self {
+        let changed = list
+            .list
+            .iter()
+            .zip(&list.generations)
+            .enumerate()
+            .filter(|(i, (slot, generation))| {
+                baseline.generations.get(*i) != Some(generation)
+                    || baseline.list.get(*i) != Some(slot)
+            })
+            .map(|(i, (slot, generation))| (i, *generation, slot.clone()))
+            .collect();
+        Self {
+            len: list.len(),
+            changed,
+        }
+    }
+    fn apply(&self, list: &mut StableList<E>) {
+        list.list.resize_with(self.len, || None);
+        list.generations.resize(self.len, 0);
+        for (i, generation, slot) in &self.changed {
+            list.list[*i] = slot.clone();
+            list.generations[*i] = *generation;
+        }
+    }
+}
+
+/// Finds each bullet's counterpart in the baseline by its [`BulletKey`](crate::BulletKey)
+fn bullets_between(baseline: &Snapshot, bullets: &[Bullet]) -> Vec<BulletDelta> {
+    let old: HashMap<_, _> = baseline
+        .bullets
+        .list
+        .iter()
+        .enumerate()
+        .map(|(i, old)| (old.key(), (i, old)))
+        .collect();
+    bullets
+        .iter()
+        .map(|bullet| {
+            let found = old.get(&bullet.key()).filter(|(_, old)| {
+                let moved = Bullet {
+                    position: bullet.position,
+                    ..(*old).clone()
+                };
+                moved == *bullet
+            });
+            match found {
+                Some((i, _)) => BulletDelta::Moved(*i, bullet.position),
+                None => BulletDelta::Full(bullet.clone()),
+            }
+        })
+        .collect()
+}
+
+impl Snapshot {
+    /// The changes that turn `baseline` into this snapshot
+    pub fn delta_from(&self, baseline: &Snapshot) -> StateDelta {
+        StateDelta {
+            baseline: baseline.time,
+            time: self.time,
+            players: SlotsDelta::between(&baseline.players, &self.players),
+            tanks: SlotsDelta::between(&baseline.tanks, &self.tanks),
+            tank_bullets: SlotsDelta::between(&baseline.tank_bullets, &self.tank_bullets),
+            bullets: bullets_between(baseline, &self.bullets.list),
+            culled: self.culled,
+        }
+    }
+    /// This snapshot with `delta` applied, `None` if the delta was built against a different one
+    pub fn apply_delta(&self, delta: &StateDelta) -> Option<Snapshot> {
+        if delta.baseline != self.time {
+            return None;
+        }
+        let bullets = delta
+            .bullets
+            .iter()
+            .map(|bullet| match bullet {
+                BulletDelta::Moved(i, position) => self.bullets.list.get(*i).map(|old| Bullet {
+                    position: *position,
+                    ..old.clone()
+                }),
+                BulletDelta::Full(bullet) => Some(bullet.clone()),
+            })
+            .collect::<Option<Vec<_>>>()?;
+        let mut snapshot = self.clone();
+        snapshot.time = delta.time;
+        delta.players.apply(&mut snapshot.players);
+        delta.tanks.apply(&mut snapshot.tanks);
+        delta.tank_bullets.apply(&mut snapshot.tank_bullets);
+        snapshot.bullets = bullets.into();
+        snapshot.culled = delta.culled;
+        Some(snapshot)
+    }
+}
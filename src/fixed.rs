@@ -0,0 +1,166 @@
+use std::ops::{Add, Neg, Sub};
+
+use serde::{Deserialize, Serialize};
+
+use euclid::Angle;
+
+use crate::Vector2D;
+
+/// Fixed point 1.0 for the results of [`BinAngle::sin`] and [`BinAngle::cos`]
+pub const TRIG_ONE: i64 = 1 << 16;
+
+/// Entries in the quarter wave sine table, the table holds one more for the end point
+const TABLE_SIZE: usize = 1024;
+const TABLE_STEP: u32 = BinAngle::QUARTER / TABLE_SIZE as u32;
+
+static SIN_TABLE: [i32; TABLE_SIZE + 1] = sin_table();
+
+/// Sine over a quarter turn, computed with integers only so every build gets the same table
+const fn sin_table() -> [i32; TABLE_SIZE + 1] {
+    const ONE: i128 = 1 << 48;
+    // pi / 2 in 48 bit fixed point
+    const HALF_PI: i128 = 442_139_859_501_777;
+    let mut table = [0; TABLE_SIZE + 1];
+    let mut i = 0;
+    while i <= TABLE_SIZE {
+        let x = HALF_PI * i as i128 / TABLE_SIZE as i128;
+        // taylor series, converges well past our precision for x <= pi / 2
+        let mut term = x;
+        let mut sum = 0;
+        let mut n = 1;
+        while n < 30 {
+            sum += term;
+            term = -term * x / ONE * x / ONE / ((n + 1) * (n + 2));
+            n += 2;
+        }
+        table[i] = ((sum * TRIG_ONE as i128 + ONE / 2) / ONE) as i32;
+        i += 1;
+    }
+    table
+}
+
+/// Sine of an angle within the first quarter turn, `angle` may be anywhere from 0 to `QUARTER`
+fn quarter_sin(angle: u32) -> i64 {
+    let (i, frac) = ((angle / TABLE_STEP) as usize, angle % TABLE_STEP);
+    let a = SIN_TABLE[i] as i64;
+    if frac == 0 {
+        return a;
+    }
+    let b = SIN_TABLE[i + 1] as i64;
+    a + (b - a) * frac as i64 / TABLE_STEP as i64
+}
+
+/// An angle in binary radians, a full turn is 2^16
+///
+/// Used for everything in the simulation so it runs the same on every machine, convert with
+/// [`BinAngle::to_angle`] for drawing.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct BinAngle(pub u16);
+
+impl BinAngle {
+    pub const QUARTER: u32 = 1 << 14;
+    pub fn zero() -> Self {
+        Self(0)
+    }
+    /// Nearest binary angle to `degrees`, only for converting data from outside the simulation
+    pub fn degrees(degrees: f32) -> Self {
+        Self((degrees as f64 / 360.0 * 65536.0).round() as i64 as u16)
+    }
+    pub fn to_angle(self) -> Angle<f32> {
+        Angle::radians(self.0 as f32 / 65536.0 * std::f32::consts::TAU)
+    }
+    /// Sine as a fixed point number, see [`TRIG_ONE`]
+    pub fn sin(self) -> i64 {
+        let angle = self.0 as u32 % Self::QUARTER;
+        match self.0 as u32 / Self::QUARTER {
+            0 => quarter_sin(angle),
+            1 => quarter_sin(Self::QUARTER - angle),
+            2 => -quarter_sin(angle),
+            _ => -quarter_sin(Self::QUARTER - angle),
+        }
+    }
+    /// Cosine as a fixed point number, see [`TRIG_ONE`]
+    pub fn cos(self) -> i64 {
+        (self + Self(Self::QUARTER as u16)).sin()
+    }
+    /// Vector pointing along this angle with the given length
+    pub fn vector(self, length: i64) -> Vector2D {
+        Vector2D::new(
+            self.cos() * length / TRIG_ONE,
+            self.sin() * length / TRIG_ONE,
+        )
+    }
+    /// `vector` rotated counterclockwise by this angle
+    pub fn rotate(self, vector: Vector2D) -> Vector2D {
+        let (sin, cos) = (self.sin(), self.cos());
+        Vector2D::new(
+            (vector.x * cos - vector.y * sin) / TRIG_ONE,
+            (vector.x * sin + vector.y * cos) / TRIG_ONE,
+        )
+    }
+    /// Direction of `vector` from the x axis, zero for a zero vector
+    pub fn of_vector(vector: Vector2D) -> Self {
+        // rotate into the first quadrant so we only have to search a quarter turn
+        let (quadrant, x, y) = match (vector.x, vector.y) {
+            (x, y) if x > 0 && y >= 0 => (0, x, y),
+            (x, y) if x <= 0 && y > 0 => (1, y, -x),
+            (x, y) if x < 0 && y <= 0 => (2, -x, -y),
+            (x, y) if x >= 0 && y < 0 => (3, -y, x),
+            _ => return Self::zero(),
+        };
+        // how far (x, y) is counterclockwise of `angle`, scaled by its length
+        let cross = |angle: u32| {
+            let angle = Self(angle as u16);
+            angle.cos() as i128 * y as i128 - angle.sin() as i128 * x as i128
+        };
+        let (mut low, mut high) = (0, Self::QUARTER);
+        while high - low > 1 {
+            let mid = (low + high) / 2;
+            if cross(mid) >= 0 {
+                low = mid;
+            } else {
+                high = mid;
+            }
+        }
+        let angle = match -cross(high) < cross(low) {
+            true => high,
+            false => low,
+        };
+        Self((quadrant * Self::QUARTER + angle) as u16)
+    }
+}
+
+impl Add for BinAngle {
+    type Output = Self;
+    fn add(self, other: Self) -> Self {
+        Self(self.0.wrapping_add(other.0))
+    }
+}
+
+impl Sub for BinAngle {
+    type Output = Self;
+    fn sub(self, other: Self) -> Self {
+        Self(self.0.wrapping_sub(other.0))
+    }
+}
+
+impl Neg for BinAngle {
+    type Output = Self;
+    fn neg(self) -> Self {
+        Self(self.0.wrapping_neg())
+    }
+}
+
+/// Integer square root, rounded down
+pub fn isqrt(n: i64) -> i64 {
+    if n < 2 {
+        return n.max(0);
+    }
+    let mut x = n;
+    let mut y = x / 2 + x % 2;
+    while y < x {
+        x = y;
+        y = (x + n / x) / 2;
+    }
+    x
+}
@@ -0,0 +1,386 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    BinAngle, Bullet, Drive, GameRules, GameState, Idx, Input, Player, PlayerStats, Point2D,
+    Snapshot, Tank, Time, Turn, Weapon,
+};
+
+/// FNV-1a over the bytes [`StableHash`] writes
+pub(crate) struct StateHasher(u64);
+
+impl StateHasher {
+    fn new() -> Self {
+        Self(0xcbf2_9ce4_8422_2325)
+    }
+    fn finish(&self) -> u64 {
+        self.0
+    }
+    fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 ^= *byte as u64;
+            self.0 = self.0.wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+    /// Every integer goes in little endian and 64 bits wide, as do lengths and enum tags
+    pub(crate) fn write_u64(&mut self, i: u64) {
+        self.write(&i.to_le_bytes())
+    }
+}
+
+/// Writes a value into a [`StateHasher`] field by field
+///
+/// Written out by hand rather than derived, as std makes no promise that what `Hash` feeds a
+/// hasher stays the same between Rust releases, and a client and server built with different
+/// ones have to agree. Enum variants get fixed tags, `Option` is tagged 0 or 1, and lists and
+/// strings start with their length.
+pub(crate) trait StableHash {
+    fn stable_hash(&self, hasher: &mut StateHasher);
+}
+
+macro_rules! stable_hash_int {
+    ($($int:ty),*) => {
+        $(impl StableHash for $int {
+            fn stable_hash(&self, hasher: &mut StateHasher) {
+                hasher.write_u64(*self as u64)
+            }
+        })*
+    };
+}
+
+stable_hash_int!(bool, u8, u16, u32, u64, usize, i64);
+
+impl<T: StableHash + ?Sized> StableHash for &T {
+    fn stable_hash(&self, hasher: &mut StateHasher) {
+        (**self).stable_hash(hasher)
+    }
+}
+
+impl StableHash for str {
+    fn stable_hash(&self, hasher: &mut StateHasher) {
+        self.len().stable_hash(hasher);
+        hasher.write(self.as_bytes());
+    }
+}
+
+impl StableHash for String {
+    fn stable_hash(&self, hasher: &mut StateHasher) {
+        self.as_str().stable_hash(hasher)
+    }
+}
+
+impl<T: StableHash> StableHash for Option<T> {
+    fn stable_hash(&self, hasher: &mut StateHasher) {
+        match self {
+            None => hasher.write_u64(0),
+            Some(value) => {
+                hasher.write_u64(1);
+                value.stable_hash(hasher);
+            }
+        }
+    }
+}
+
+impl<T: StableHash> StableHash for [T] {
+    fn stable_hash(&self, hasher: &mut StateHasher) {
+        self.len().stable_hash(hasher);
+        for value in self {
+            value.stable_hash(hasher);
+        }
+    }
+}
+
+impl<T: StableHash> StableHash for Vec<T> {
+    fn stable_hash(&self, hasher: &mut StateHasher) {
+        self[..].stable_hash(hasher)
+    }
+}
+
+impl<A: StableHash, B: StableHash> StableHash for (A, B) {
+    fn stable_hash(&self, hasher: &mut StateHasher) {
+        self.0.stable_hash(hasher);
+        self.1.stable_hash(hasher);
+    }
+}
+
+impl StableHash for Point2D {
+    fn stable_hash(&self, hasher: &mut StateHasher) {
+        self.x.stable_hash(hasher);
+        self.y.stable_hash(hasher);
+    }
+}
+
+impl StableHash for BinAngle {
+    fn stable_hash(&self, hasher: &mut StateHasher) {
+        self.0.stable_hash(hasher)
+    }
+}
+
+impl StableHash for Time {
+    fn stable_hash(&self, hasher: &mut StateHasher) {
+        self.0.stable_hash(hasher)
+    }
+}
+
+impl<E> StableHash for Idx<'_, E> {
+    fn stable_hash(&self, hasher: &mut StateHasher) {
+        self.0.stable_hash(hasher);
+        self.1.stable_hash(hasher);
+    }
+}
+
+impl StableHash for Weapon {
+    fn stable_hash(&self, hasher: &mut StateHasher) {
+        hasher.write_u64(match self {
+            Weapon::Cannon => 0,
+            Weapon::MachineGun => 1,
+            Weapon::Shotgun => 2,
+            Weapon::HeavyShell => 3,
+        })
+    }
+}
+
+impl StableHash for Turn {
+    fn stable_hash(&self, hasher: &mut StateHasher) {
+        hasher.write_u64(match self {
+            Turn::Left => 0,
+            Turn::Right => 1,
+        })
+    }
+}
+
+impl StableHash for Drive {
+    fn stable_hash(&self, hasher: &mut StateHasher) {
+        hasher.write_u64(match self {
+            Drive::Forward => 0,
+            Drive::Reverse => 1,
+        })
+    }
+}
+
+impl StableHash for Input {
+    fn stable_hash(&self, hasher: &mut StateHasher) {
+        let Input {
+            drive,
+            rotate,
+            turret,
+            fire,
+            weapon,
+            seq,
+        } = self;
+        drive.stable_hash(hasher);
+        rotate.stable_hash(hasher);
+        turret.stable_hash(hasher);
+        fire.stable_hash(hasher);
+        weapon.stable_hash(hasher);
+        seq.stable_hash(hasher);
+    }
+}
+
+impl StableHash for Tank {
+    fn stable_hash(&self, hasher: &mut StateHasher) {
+        let Tank {
+            player,
+            team,
+            position,
+            angle,
+            turret_angle,
+            health,
+            max_health,
+            weapon,
+        } = self;
+        player.stable_hash(hasher);
+        team.stable_hash(hasher);
+        position.stable_hash(hasher);
+        angle.stable_hash(hasher);
+        turret_angle.stable_hash(hasher);
+        health.stable_hash(hasher);
+        max_health.stable_hash(hasher);
+        weapon.stable_hash(hasher);
+    }
+}
+
+impl StableHash for Bullet {
+    fn stable_hash(&self, hasher: &mut StateHasher) {
+        let Bullet {
+            position,
+            angle,
+            damage,
+            birth,
+            player,
+            team,
+            kind,
+            bounces,
+            pellet,
+        } = self;
+        position.stable_hash(hasher);
+        angle.stable_hash(hasher);
+        damage.stable_hash(hasher);
+        birth.stable_hash(hasher);
+        player.stable_hash(hasher);
+        team.stable_hash(hasher);
+        kind.stable_hash(hasher);
+        bounces.stable_hash(hasher);
+        pellet.stable_hash(hasher);
+    }
+}
+
+impl StableHash for Player {
+    fn stable_hash(&self, hasher: &mut StateHasher) {
+        let Player {
+            name,
+            team,
+            input,
+            respawn,
+            stats,
+        } = self;
+        name.stable_hash(hasher);
+        team.stable_hash(hasher);
+        input.stable_hash(hasher);
+        respawn.stable_hash(hasher);
+        stats.stable_hash(hasher);
+    }
+}
+
+impl StableHash for PlayerStats {
+    fn stable_hash(&self, hasher: &mut StateHasher) {
+        let PlayerStats {
+            kills,
+            deaths,
+            damage_dealt,
+            shots_fired,
+            shots_hit,
+        } = self;
+        kills.stable_hash(hasher);
+        deaths.stable_hash(hasher);
+        damage_dealt.stable_hash(hasher);
+        shots_fired.stable_hash(hasher);
+        shots_hit.stable_hash(hasher);
+    }
+}
+
+impl StableHash for GameRules {
+    fn stable_hash(&self, hasher: &mut StateHasher) {
+        let GameRules {
+            respawn_delay,
+            bullets_collide,
+            teams,
+            friendly_fire,
+        } = self;
+        respawn_delay.stable_hash(hasher);
+        bullets_collide.stable_hash(hasher);
+        teams.stable_hash(hasher);
+        friendly_fire.stable_hash(hasher);
+    }
+}
+
+impl StableHash for Entity {
+    fn stable_hash(&self, hasher: &mut StateHasher) {
+        match self {
+            Entity::Time => hasher.write_u64(0),
+            Entity::Rules => hasher.write_u64(5),
+            Entity::Player(idx) => {
+                hasher.write_u64(1);
+                idx.stable_hash(hasher);
+            }
+            Entity::Tank(idx) => {
+                hasher.write_u64(2);
+                idx.stable_hash(hasher);
+            }
+            Entity::TankBullets(idx) => {
+                hasher.write_u64(3);
+                idx.stable_hash(hasher);
+            }
+            Entity::Bullet(i) => {
+                hasher.write_u64(4);
+                i.stable_hash(hasher);
+            }
+        }
+    }
+}
+
+fn hash_of<T: StableHash + ?Sized>(value: &T) -> u64 {
+    let mut hasher = StateHasher::new();
+    value.stable_hash(&mut hasher);
+    hasher.finish()
+}
+
+/// A piece of a [`GameState`] that is hashed on its own, to point at where two states differ
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Entity {
+    Time,
+    /// The [`GameRules`], which come with the welcome rather than with each snapshot
+    Rules,
+    Player(Idx<'static, Player>),
+    Tank(Idx<'static, Tank>),
+    /// Hits waiting to be applied to a tank
+    TankBullets(Idx<'static, Tank>),
+    /// Position in the bullet list
+    Bullet(usize),
+}
+
+impl GameState {
+    /// Hash of everything the simulation depends on, the same on every machine
+    ///
+    /// The level and collision map are left out as they are rebuilt from the rest, so this is the
+    /// same as the hash of [`GameState::snapshot`] with the state's rules.
+    pub fn content_hash(&self) -> u64 {
+        self.snapshot().content_hash(&self.rules)
+    }
+    /// The first entity that differs between the two states, if any
+    pub fn first_difference(&self, other: &GameState) -> Option<Entity> {
+        let ours = self.snapshot().entity_hashes(&self.rules);
+        let theirs = other.snapshot().entity_hashes(&other.rules);
+        let lookup = |hashes: &[(Entity, u64)]| hashes.iter().cloned().collect::<HashMap<_, _>>();
+        let (our_lookup, their_lookup) = (lookup(&ours), lookup(&theirs));
+        (ours.iter())
+            .find(|(entity, hash)| their_lookup.get(entity) != Some(hash))
+            // anything they have that we don't
+            .or_else(|| {
+                theirs
+                    .iter()
+                    .find(|(entity, _)| !our_lookup.contains_key(entity))
+            })
+            .map(|(entity, _)| *entity)
+    }
+}
+
+impl Snapshot {
+    /// Hash of everything in the snapshot along with the match's rules, see
+    /// [`GameState::content_hash`]
+    pub fn content_hash(&self, rules: &GameRules) -> u64 {
+        hash_of(&self.entity_hashes(rules)[..])
+    }
+    /// Hash of each entity in a fixed order
+    pub fn entity_hashes(&self, rules: &GameRules) -> Vec<(Entity, u64)> {
+        let players = self
+            .players
+            .into_iter()
+            .map(|(idx, player)| (Entity::Player(idx), hash_of(&player)));
+        let tanks = self
+            .tanks
+            .into_iter()
+            .map(|(idx, tank)| (Entity::Tank(idx), hash_of(&tank)));
+        let tank_bullets = self.tank_bullets.into_iter().map(|(idx, bullets)| {
+            let bullets: Option<&[Bullet]> = bullets.map(|b| &b[..]);
+            (
+                Entity::TankBullets(Idx::new(idx.0, idx.1)),
+                hash_of(&bullets),
+            )
+        });
+        let bullets = self
+            .bullets
+            .list
+            .iter()
+            .enumerate()
+            .map(|(i, bullet)| (Entity::Bullet(i), hash_of(bullet)));
+        std::iter::once((Entity::Time, hash_of(&self.time)))
+            .chain(std::iter::once((Entity::Rules, hash_of(rules))))
+            .chain(players)
+            .chain(tanks)
+            .chain(tank_bullets)
+            .chain(bullets)
+            .collect()
+    }
+}
@@ -1,18 +1,30 @@
 #![feature(min_type_alias_impl_trait)]
 #![feature(array_chunks)]
 
-use std::f32::consts::TAU;
+use std::collections::HashMap;
 use std::mem;
+use std::sync::Arc;
 
 use serde::{Deserialize, Serialize};
 
-use euclid::{Angle, Length, Scale};
+use euclid::{Length, Scale};
 
+#[cfg(feature = "bench")]
+mod bench;
 #[cfg(feature = "client")]
 mod client;
+mod config;
+mod delta;
+mod fixed;
+mod hash;
+mod map;
+mod protocol;
 #[cfg(feature = "server")]
 mod server;
+mod weapon;
 
+#[cfg(feature = "bench")]
+pub use bench::run_bench;
 #[cfg(all(feature = "druid_backend", feature = "client"))]
 pub use client::DruidEventLoop;
 #[cfg(all(feature = "minifb_backend", feature = "client"))]
@@ -23,8 +35,15 @@ pub use client::PathfinderEventLoop;
 pub use client::PixelsEventLoop;
 #[cfg(feature = "client")]
 pub use client::{run_client, NoopRenderer};
+pub use config::{SimulationConfig, WeaponSpecs};
+pub use delta::StateDelta;
+pub use fixed::BinAngle;
+pub use hash::Entity;
+pub use map::{Level, Map};
+pub use protocol::{ClientMessage, ServerMessage, MAX_BASELINE_AGE, PROTOCOL_VERSION};
 #[cfg(feature = "server")]
 pub use server::run_server;
+pub use weapon::{Weapon, WeaponSpec, WeaponState};
 
 /// Gm = Game meter
 pub enum Gm {}
@@ -40,62 +59,74 @@ type Transform2D<T = f32, Src = Gm, Dst = Gm> = euclid::Rotation2D<T, Src, Dst>;
 
 pub const GM_ONE_PIXEL: i64 = 10000;
 pub const GM_SCALE: Scale<i64, Pixel, Gm> = Scale::new(GM_ONE_PIXEL);
-const UPDATES_PER_SECOND: i64 = 60;
 
-#[derive(Clone, Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct Tank {
     player: Idx<'static, Player>,
+    team: Option<u8>,
     position: Point2D,
-    angle: Angle<f32>,
-    turret_angle: Angle<f32>,
+    angle: BinAngle,
+    turret_angle: BinAngle,
     health: i64,
+    max_health: i64,
+    weapon: WeaponState,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
 enum TankUpdate {
+    /// Destroyed by the given player
     Dead(Idx<'static, Player>),
+    /// Owning player has left
+    Removed,
     Alive(Tank),
-    Fire(Tank, Bullet),
+    Fire(Tank, Vec<Bullet>),
 }
 
-#[derive(Clone, Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 enum Turn {
     Left,
     Right,
 }
 
-#[derive(Clone, Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 enum Drive {
     Forward,
     Reverse,
 }
 
-#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
 pub struct Input {
     drive: Option<Drive>,
     rotate: Option<Turn>,
     turret: Option<Turn>,
     fire: bool,
+    weapon: Weapon,
     seq: usize,
 }
 
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 struct TankHitbox {
     center: Point2D,
-    angle: Angle<f32>,
+    angle: BinAngle,
+    /// Half the width of the square
+    half_size: i64,
 }
 
 impl TankHitbox {
-    const TANK_SIZE: i64 = 20 * GM_ONE_PIXEL;
-    const ENVELOPE_LIMIT: i64 = (Self::TANK_SIZE as f64 * std::f64::consts::SQRT_2) as i64;
+    /// Furthest a corner can be from the center, rounded up
+    fn envelope_limit(&self) -> i64 {
+        self.half_size * 1415 / 1000
+    }
     fn distance_relative(&self, relative_vec: &Vector2D) -> i64 {
-        let aabb = Box2D::<_, Gm>::zero().inflate(Self::TANK_SIZE, Self::TANK_SIZE);
-        rstar::AABB::from_corners(aabb.min, aabb.max).distance_2(
-            &Rotation2D::new(self.angle)
-                .inverse()
-                .transform_vector(relative_vec.to_f32())
-                .to_i64()
-                .to_point(),
+        let aabb = Box2D::<_, Gm>::zero().inflate(self.half_size, self.half_size);
+        rstar::AABB::from_corners(aabb.min, aabb.max)
+            .distance_2(&(-self.angle).rotate(*relative_vec).to_point())
+    }
+    fn corners(&self) -> [Point2D; 4] {
+        rectangle_corners(
+            self.center,
+            Size2D::new(self.half_size, self.half_size),
+            self.angle,
         )
     }
     //pub fn box(&self) -> (Box2D, Rotation2D, Translation2D) {
@@ -111,7 +142,7 @@ impl rstar::RTreeObject for TankHitbox {
     fn envelope(&self) -> Self::Envelope {
         let aabb = Box2D::zero()
             .translate(self.center.to_vector())
-            .inflate(Self::ENVELOPE_LIMIT, Self::ENVELOPE_LIMIT);
+            .inflate(self.envelope_limit(), self.envelope_limit());
         rstar::AABB::from_corners(aabb.min, aabb.max)
     }
 }
@@ -122,7 +153,7 @@ impl rstar::PointDistance for TankHitbox {
     }
     fn contains_point(&self, point: &Point2D) -> bool {
         let vec = *point - self.center;
-        if vec.square_length() > (Self::ENVELOPE_LIMIT * Self::ENVELOPE_LIMIT * 2) {
+        if vec.square_length() > (self.envelope_limit() * self.envelope_limit() * 2) {
             false
         } else {
             self.distance_relative(&vec) <= 0
@@ -130,93 +161,326 @@ impl rstar::PointDistance for TankHitbox {
     }
 }
 
+/// Corners of a rectangle around `center` with the given half extents, rotated by `angle`
+fn rectangle_corners(center: Point2D, half_size: Size2D, angle: BinAngle) -> [Point2D; 4] {
+    let corner = |x: i64, y: i64| center + angle.rotate(Vector2D::new(x, y));
+    [
+        corner(-half_size.width, -half_size.height),
+        corner(half_size.width, -half_size.height),
+        corner(half_size.width, half_size.height),
+        corner(-half_size.width, half_size.height),
+    ]
+}
+
+/// Projects a convex polygon onto `axis`, returning the (min, max) of the projection
+fn project(polygon: &[Point2D], axis: Vector2D) -> (i64, i64) {
+    polygon
+        .iter()
+        .map(|p| p.to_vector().dot(axis))
+        .fold((i64::MAX, i64::MIN), |(min, max), d| {
+            (min.min(d), max.max(d))
+        })
+}
+
+/// Separating axis test between two convex polygons
+///
+/// Returns the smallest translation that moves `a` out of `b`, or `None` if they do not overlap
+fn separate(a: &[Point2D], b: &[Point2D]) -> Option<Vector2D> {
+    let mut best: Option<(i64, Vector2D)> = None;
+    for polygon in [a, b].iter() {
+        for (i, point) in polygon.iter().enumerate() {
+            let edge = polygon[(i + 1) % polygon.len()] - *point;
+            let axis = Vector2D::new(-edge.y, edge.x);
+            if axis == Vector2D::zero() {
+                continue;
+            }
+            let (a_min, a_max) = project(a, axis);
+            let (b_min, b_max) = project(b, axis);
+            if a_max <= b_min || b_max <= a_min {
+                return None;
+            }
+            // push a out whichever side of b is closer
+            let (overlap, direction) = if a_max - b_min < b_max - a_min {
+                (a_max - b_min, -1)
+            } else {
+                (b_max - a_min, 1)
+            };
+            let length = fixed::isqrt(axis.square_length());
+            let depth = (overlap + length - 1) / length;
+            if best.map(|(d, _)| depth < d).unwrap_or(true) {
+                // overshoot by a couple of Gm so rounding still leaves us just outside b
+                let push = axis * (direction * (depth + 2)) / length;
+                best = Some((depth, push));
+            }
+        }
+    }
+    best.map(|(_, push)| push)
+}
+
+/// Whether the fraction `a.0 / a.1` is less than `b.0 / b.1`, denominators must be positive
+fn fraction_lt(a: (i64, i64), b: (i64, i64)) -> bool {
+    (a.0 as i128 * b.1 as i128) < (b.0 as i128 * a.1 as i128)
+}
+
+/// How far along the segment `from`..`to` it first touches a convex polygon
+///
+/// Returned as a fraction `(numerator, denominator)` between 0 and 1, a segment starting inside
+/// the polygon enters it at 0.
+fn segment_entry(from: Point2D, to: Point2D, polygon: &[Point2D]) -> Option<(i64, i64)> {
+    let center = polygon
+        .iter()
+        .fold(Vector2D::zero(), |sum, p| sum + p.to_vector())
+        / polygon.len() as i64;
+    let direction = to - from;
+    let (mut enter, mut exit) = ((0, 1), (1, 1));
+    for (i, point) in polygon.iter().enumerate() {
+        let edge = polygon[(i + 1) % polygon.len()] - *point;
+        let mut normal = Vector2D::new(-edge.y, edge.x);
+        if normal.dot(*point - center.to_point()) < 0 {
+            normal = -normal;
+        }
+        // inside this edge while normal . (from + t * direction - point) <= 0
+        let distance = normal.dot(*point - from);
+        let closing = normal.dot(direction);
+        if closing == 0 {
+            if distance < 0 {
+                return None;
+            }
+        } else if closing < 0 {
+            if fraction_lt(enter, (-distance, -closing)) {
+                enter = (-distance, -closing);
+            }
+        } else if fraction_lt((distance, closing), exit) {
+            exit = (distance, closing);
+        }
+        if fraction_lt(exit, enter) {
+            return None;
+        }
+    }
+    Some(enter)
+}
+
 impl Tank {
-    fn tick(&self, state: &GameState, bullets: &[Bullet]) -> TankUpdate {
+    fn spawn(
+        player: Idx<'static, Player>,
+        team: Option<u8>,
+        position: Point2D,
+        angle: BinAngle,
+        config: &SimulationConfig,
+    ) -> Self {
+        Self {
+            player,
+            team,
+            position,
+            angle,
+            turret_angle: angle,
+            health: config.tank_health,
+            max_health: config.tank_health,
+            weapon: WeaponState::full(Weapon::default(), config),
+        }
+    }
+    /// Remaining health from 0 to 1, for health bars
+    pub fn health_fraction(&self) -> f32 {
+        self.health as f32 / self.max_health as f32
+    }
+    fn tick(&self, idx: Idx<'static, Tank>, state: &GameState, bullets: &[Bullet]) -> TankUpdate {
         let hp = match bullets.into_iter().try_fold(self.health, |hp, bullet| {
             let hp = hp - bullet.damage;
-            //if hp <= 0 {
-            //    Err(bullet.player)
-            //} else {
-            Ok(hp)
-            //}
+            if hp <= 0 {
+                Err(bullet.player)
+            } else {
+                Ok(hp)
+            }
         }) {
             Err(player) => return TankUpdate::Dead(player),
             Ok(hp) => hp,
         };
         let input = &match &state.players[self.player] {
-            None => return TankUpdate::Dead(self.player),
+            None => return TankUpdate::Removed,
             Some(s) => s,
         }
         .input;
-        const TURN_RATE: Angle<f32> = Angle {
-            radians: TAU * (0.5 / UPDATES_PER_SECOND as f32),
-        };
-        let angle = (self.angle
+        let config = &state.config;
+        let turn_rate = config.turn_per_tick();
+        let speed = config.per_tick(config.tank_speed);
+        let angle = self.angle
             + match input.rotate {
-                Some(Turn::Left) => TURN_RATE,
-                Some(Turn::Right) => -TURN_RATE,
-                None => Angle::zero(),
-            })
-        .positive();
-        //% TAU;
-        let turret_angle = (self.turret_angle
+                Some(Turn::Left) => turn_rate,
+                Some(Turn::Right) => -turn_rate,
+                None => BinAngle::zero(),
+            };
+        let turret_angle = self.turret_angle
             + match input.turret {
-                Some(Turn::Left) => TURN_RATE,
-                Some(Turn::Right) => -TURN_RATE,
-                None => Angle::zero(),
-            })
-        .positive();
-        //% TAU;
+                Some(Turn::Left) => turn_rate,
+                Some(Turn::Right) => -turn_rate,
+                None => BinAngle::zero(),
+            };
         let position = self.position
             + match input.drive {
-                Some(Drive::Forward) => {
-                    (Vector2D::from_angle_and_length(angle, 280.0) * GM_SCALE.cast()).to_i64()
-                        / UPDATES_PER_SECOND
-                }
-                Some(Drive::Reverse) => {
-                    (-Vector2D::from_angle_and_length(angle, 280.0) * GM_SCALE.cast()).to_i64()
-                        / UPDATES_PER_SECOND
-                }
+                Some(Drive::Forward) => angle.vector(speed),
+                Some(Drive::Reverse) => -angle.vector(speed),
                 None => Vector2D::zero(),
             };
 
-        // TODO
-        //let position = if let Some(_) = state.collide(position) {
-        //    self.position
-        //} else {
-        //    position
-        //};
+        let (angle, position) = self.resolve_movement(idx, state, angle, position);
+        let (weapon, fired) = self.weapon.tick(config, input.fire, input.weapon);
         let tank = Tank {
             player: self.player,
+            team: self.team,
             position,
             angle,
             turret_angle,
             health: hp,
+            max_health: self.max_health,
+            weapon,
         };
-        match input.fire {
+        match fired {
             true => TankUpdate::Fire(
                 tank,
-                Bullet {
-                    position: self.position
-                        + Vector2D::from_angle_and_length(turret_angle, 40.0).to_i64() * GM_SCALE,
-                    angle: self.turret_angle,
-                    damage: 10,
-                    player: self.player,
-                    birth: state.time,
-                },
+                self.weapon.kind().fire(
+                    config,
+                    self.player,
+                    self.team,
+                    self.position,
+                    self.turret_angle,
+                    state.time,
+                ),
             ),
             false => TankUpdate::Alive(tank),
         }
     }
-    fn hitbox(&self) -> TankHitbox {
+    fn hitbox(&self, config: &SimulationConfig) -> TankHitbox {
         TankHitbox {
             center: self.position,
             angle: self.angle,
+            half_size: config.tank_size * GM_ONE_PIXEL,
+        }
+    }
+    /// Pushes the tank out of anything it would overlap after moving, so it slides along
+    /// whatever it drove into instead of passing through it
+    fn resolve_movement(
+        &self,
+        idx: Idx<'static, Tank>,
+        state: &GameState,
+        angle: BinAngle,
+        position: Point2D,
+    ) -> (BinAngle, Point2D) {
+        const MAX_ITERATIONS: usize = 4;
+        let mut hitbox = TankHitbox {
+            center: position,
+            angle,
+            ..self.hitbox(&state.config)
+        };
+        for _ in 0..MAX_ITERATIONS {
+            match state.collision.penetration(&hitbox, idx) {
+                None => return (hitbox.angle, hitbox.center),
+                Some(push) => hitbox.center += push,
+            }
+        }
+        // wedged in, try turning on the spot before giving up on the move entirely
+        let hitbox = TankHitbox {
+            angle,
+            ..self.hitbox(&state.config)
+        };
+        match state.collision.penetration(&hitbox, idx) {
+            None => (angle, self.position),
+            Some(_) => (self.angle, self.position),
         }
     }
 }
 
-#[derive(Clone, Debug, Serialize, Deserialize)]
+/// Static level geometry, a rectangle that may be rotated about its center
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Wall {
+    center: Point2D,
+    half_size: Size2D,
+    angle: BinAngle,
+}
+
+impl Wall {
+    /// Thickness of the walls enclosing the arena
+    const BOUNDARY_THICKNESS: i64 = 100 * GM_ONE_PIXEL;
+    pub fn axis_aligned(rect: Box2D) -> Self {
+        Self {
+            center: rect.center(),
+            half_size: rect.size() / 2,
+            angle: BinAngle::zero(),
+        }
+    }
+    pub fn rotated(center: Point2D, size: Size2D, angle: BinAngle) -> Self {
+        Self {
+            center,
+            half_size: size / 2,
+            angle,
+        }
+    }
+    /// Walls on each side of `arena`, keeping everything inside it
+    pub fn boundary(arena: Box2D) -> [Self; 4] {
+        let t = Self::BOUNDARY_THICKNESS;
+        let (min, max) = (arena.min, arena.max);
+        [
+            Self::axis_aligned(Box2D::new(
+                Point2D::new(min.x - t, min.y - t),
+                Point2D::new(min.x, max.y + t),
+            )),
+            Self::axis_aligned(Box2D::new(
+                Point2D::new(max.x, min.y - t),
+                Point2D::new(max.x + t, max.y + t),
+            )),
+            Self::axis_aligned(Box2D::new(
+                Point2D::new(min.x, min.y - t),
+                Point2D::new(max.x, min.y),
+            )),
+            Self::axis_aligned(Box2D::new(
+                Point2D::new(min.x, max.y),
+                Point2D::new(max.x, max.y + t),
+            )),
+        ]
+    }
+    fn corners(&self) -> [Point2D; 4] {
+        rectangle_corners(self.center, self.half_size, self.angle)
+    }
+    /// `point` in the wall's own unrotated coordinate space
+    fn to_local(&self, point: &Point2D) -> Point2D {
+        (-self.angle).rotate(*point - self.center).to_point()
+    }
+    /// Outward normal, `GM_ONE_PIXEL` long, of the face closest to `point` outside the wall
+    fn normal_facing(&self, point: &Point2D) -> Vector2D {
+        let local = self.to_local(point);
+        let (excess_x, excess_y) = (
+            local.x.abs() - self.half_size.width,
+            local.y.abs() - self.half_size.height,
+        );
+        let normal = if excess_x > excess_y {
+            Vector2D::new(local.x.signum(), 0)
+        } else {
+            Vector2D::new(0, local.y.signum())
+        };
+        self.angle.rotate(normal * GM_ONE_PIXEL)
+    }
+    fn local_aabb(&self) -> rstar::AABB<Point2D> {
+        let half_size = self.half_size.to_vector().to_point();
+        rstar::AABB::from_corners(-half_size, half_size)
+    }
+}
+
+impl rstar::RTreeObject for Wall {
+    type Envelope = rstar::AABB<Point2D>;
+    fn envelope(&self) -> Self::Envelope {
+        rstar::AABB::from_points(self.corners().iter())
+    }
+}
+
+impl rstar::PointDistance for Wall {
+    fn distance_2(&self, point: &Point2D) -> i64 {
+        self.local_aabb().distance_2(&self.to_local(point))
+    }
+    fn contains_point(&self, point: &Point2D) -> bool {
+        rstar::Envelope::contains_point(&self.local_aabb(), &self.to_local(point))
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 struct ElementList<E> {
     list: Vec<E>,
 }
@@ -227,30 +491,47 @@ impl<E> ElementList<E> {
     }
 }
 
+impl<E> Default for ElementList<E> {
+    fn default() -> Self {
+        Self { list: vec![] }
+    }
+}
+
 impl<E> From<Vec<E>> for ElementList<E> {
     fn from(list: Vec<E>) -> Self {
         Self { list }
     }
 }
+/// Position of an element in a list, along with the generation of the slot it was taken from
+///
+/// A [`StableList`] bumps a slot's generation whenever it is reused, so an index to an element
+/// that has since been removed won't find whatever took its place.
 #[derive(Debug, Serialize, Deserialize)]
-struct Idx<'a, E>(usize, std::marker::PhantomData<&'a ElementList<E>>);
+pub struct Idx<'a, E>(usize, u32, std::marker::PhantomData<&'a ElementList<E>>);
+
+impl<'a, E> Idx<'a, E> {
+    fn new(index: usize, generation: u32) -> Self {
+        Self(index, generation, Default::default())
+    }
+}
 
 impl<'a, T> Clone for Idx<'a, T> {
     fn clone(&self) -> Self {
-        Self(self.0, self.1.clone())
+        Self(self.0, self.1, self.2.clone())
     }
 }
 impl<'a, T> Copy for Idx<'a, T> {}
 
 impl<'a, E> PartialEq for Idx<'a, E> {
     fn eq(&self, other: &Self) -> bool {
-        self.0 == other.0
+        self.0 == other.0 && self.1 == other.1
     }
 }
 impl<'a, E> Eq for Idx<'a, E> {}
 impl<'a, E> std::hash::Hash for Idx<'a, E> {
     fn hash<H: std::hash::Hasher>(&self, hasher: &mut H) {
-        self.0.hash(hasher)
+        self.0.hash(hasher);
+        self.1.hash(hasher);
     }
 }
 
@@ -261,7 +542,7 @@ impl<'a, E> std::iter::IntoIterator for &'a ElementList<E> {
         self.list
             .iter()
             .enumerate()
-            .map(|(i, t)| (Idx(i, Default::default()), t))
+            .map(|(i, t)| (Idx::new(i, 0), t))
     }
 }
 
@@ -272,9 +553,15 @@ impl<'a, E> std::ops::Index<Idx<'a, E>> for ElementList<E> {
     }
 }
 
-#[derive(Clone, Debug, Serialize, Deserialize)]
+/// A list whose elements keep their index when others are removed
+///
+/// Indexing with a stale [`Idx`] gives `None` and mutably indexing with one panics, use
+/// [`StableList::get_mut`] where an index may have gone stale.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 struct StableList<E> {
     list: Vec<Option<E>>,
+    /// Generation of each slot, bumped every time the slot is reused
+    generations: Vec<u32>,
 }
 
 impl<E> StableList<E> {
@@ -284,21 +571,69 @@ impl<E> StableList<E> {
     pub fn push(&mut self, value: E) -> Idx<'static, E> {
         let i = if let Some((i, x)) = self.list.iter_mut().enumerate().find(|(i, x)| x.is_none()) {
             *x = Some(value);
+            self.generations[i] = self.generations[i].wrapping_add(1);
             i
         } else {
             let i = self.list.len();
             self.list.push(Some(value));
+            self.generations.push(0);
             i
         };
-        Idx(i, Default::default())
+        Idx::new(i, self.generations[i])
     }
+    /// Empties the slot `idx` points at, nothing happens if it's stale
     pub fn remove(&mut self, idx: &Idx<'static, E>) -> Option<E> {
-        mem::take(&mut self.list[idx.0])
+        match self.is_current(*idx) {
+            true => self.list[idx.0].take(),
+            false => None,
+        }
+    }
+    /// The element `idx` points at, `None` if it's empty or stale
+    pub fn get(&self, idx: Idx<'static, E>) -> Option<&E> {
+        self[idx].as_ref()
+    }
+    /// The element `idx` points at, `None` if it's empty or stale
+    pub fn get_mut(&mut self, idx: Idx<'static, E>) -> Option<&mut E> {
+        match self.is_current(idx) {
+            true => self.list[idx.0].as_mut(),
+            false => None,
+        }
+    }
+    /// A copy with only the slots `keep` returns true for, the rest left empty
+    fn filtered(&self, keep: impl Fn(usize) -> bool) -> Self
+    where
+        E: Clone,
+    {
+        Self {
+            list: self
+                .list
+                .iter()
+                .enumerate()
+                .map(|(i, slot)| slot.as_ref().filter(|_| keep(i)).cloned())
+                .collect(),
+            generations: self.generations.clone(),
+        }
+    }
+    /// Puts `value` in the slot `idx` points at, growing the list to fit and taking its generation
+    fn insert(&mut self, idx: Idx<'static, E>, value: E) {
+        if self.list.len() <= idx.0 {
+            self.list.resize_with(idx.0 + 1, || None);
+            self.generations.resize(idx.0 + 1, 0);
+        }
+        self.list[idx.0] = Some(value);
+        self.generations[idx.0] = idx.1;
+    }
+    /// Whether `idx` was handed out for the current generation of its slot
+    fn is_current(&self, idx: Idx<'static, E>) -> bool {
+        self.generations.get(idx.0) == Some(&idx.1)
     }
 }
 impl<E> From<Vec<Option<E>>> for StableList<E> {
     fn from(list: Vec<Option<E>>) -> Self {
-        Self { list }
+        Self {
+            generations: vec![0; list.len()],
+            list,
+        }
     }
 }
 
@@ -310,45 +645,100 @@ impl<'a, E: 'static> std::iter::IntoIterator for &'a StableList<E> {
             .iter()
             .enumerate()
             //.filter_map(|(i, t)| t.as_ref().map(|t| (i, t)))
-            .map(|(i, t)| (Idx(i, Default::default()), t.as_ref()))
+            .zip(&self.generations)
+            .map(|((i, t), generation)| (Idx::new(i, *generation), t.as_ref()))
     }
 }
 
 impl<E> std::ops::Index<Idx<'static, E>> for StableList<E> {
     type Output = Option<E>;
     fn index(&self, idx: Idx<'static, E>) -> &Self::Output {
-        &self.list[idx.0]
+        match self.is_current(idx) {
+            true => &self.list[idx.0],
+            false => &None,
+        }
     }
 }
 impl<E> std::ops::IndexMut<Idx<'static, E>> for StableList<E> {
     fn index_mut(&mut self, idx: Idx<'static, E>) -> &mut Self::Output {
+        assert!(
+            self.is_current(idx),
+            "writing through stale index {} of generation {}",
+            idx.0,
+            idx.1
+        );
         &mut self.list[idx.0]
     }
 }
 
-#[derive(Clone, Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct Bullet {
     position: Point2D,
-    angle: Angle<f32>,
+    angle: BinAngle,
     damage: i64,
     birth: Time,
     player: Idx<'static, Player>,
+    /// Team of the tank that fired it
+    team: Option<u8>,
+    kind: Weapon,
+    /// Times the bullet can still bounce off a wall before it stops at one
+    bounces: u32,
+    /// Which of its shot's pellets this is
+    pellet: u32,
+}
+
+/// Tells bullets apart from one tick to the next, a player fires at most one shot a tick
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+struct BulletKey {
+    /// Slot and generation of the player who fired it
+    player: (usize, u32),
+    birth: u64,
+    pellet: u32,
 }
 
 impl Bullet {
+    fn key(&self) -> BulletKey {
+        BulletKey {
+            player: (self.player.0, self.player.1),
+            birth: self.birth.0,
+            pellet: self.pellet,
+        }
+    }
+    fn velocity(&self, config: &SimulationConfig) -> Vector2D {
+        self.angle
+            .vector(config.per_tick(config.weapon(self.kind).speed))
+    }
     fn tick(&self, state: &GameState) -> BulletUpdate {
-        let position = self.position
-            + (Vector2D::from_angle_and_length(self.angle, 1000.0) * GM_SCALE.cast()).to_i64()
-                / UPDATES_PER_SECOND;
-        if state.time.0 - self.birth.0 > 60 * 10 {
+        let config = &state.config;
+        let velocity = self.velocity(config);
+        let position = self.position + velocity;
+        let lifetime = config.ticks(config.weapon(self.kind).lifetime) as u64;
+        if state.time.0 - self.birth.0 > lifetime {
             return BulletUpdate::Dead;
         }
-        //if position.square_length() > (1000000 * 1000) {
-        //    return BulletUpdate::Dead;
-        //}
-        match state.collide(position) {
-            Some(Collision::Tank(tank)) => BulletUpdate::Hit(tank),
-            Some(Collision::Arena) => BulletUpdate::Dead,
+        match state.sweep(self.position, position) {
+            Some((Collision::Tank(tank), hit)) => BulletUpdate::Hit(tank, hit),
+            Some((Collision::Wall(wall), _)) if self.bounces > 0 => {
+                let normal = wall.normal_facing(&self.position);
+                // v - 2(v.n)n / |n|^2, kept in integers until the angle is taken
+                let reflected =
+                    velocity - normal * (2 * velocity.dot(normal)) / normal.square_length();
+                let bounced = Self {
+                    angle: BinAngle::of_vector(reflected),
+                    bounces: self.bounces - 1,
+                    ..self.clone()
+                };
+                // stay where we are this tick if the bounce would put us inside something else
+                let position = match state.sweep(self.position, self.position + reflected) {
+                    None => self.position + reflected,
+                    Some(_) => self.position,
+                };
+                BulletUpdate::Move(Self {
+                    position,
+                    ..bounced
+                })
+            }
+            Some((Collision::Wall(_), hit)) => BulletUpdate::Impact(hit),
             None => BulletUpdate::Move(Self {
                 position,
                 ..self.clone()
@@ -357,33 +747,240 @@ impl Bullet {
     }
 }
 
-#[derive(Clone, Debug, Serialize, Deserialize)]
-struct Player {
+/// A bullet and where it travels over the coming tick, indexed to find bullets near a point or
+/// crossing each other
+#[derive(Clone, Debug, PartialEq)]
+struct BulletPath {
+    bullet: Bullet,
+    velocity: Vector2D,
+}
+
+impl BulletPath {
+    /// Bullets closer than this at any point during a tick destroy each other
+    const INTERCEPT_RADIUS: i64 = 4 * GM_ONE_PIXEL;
+    fn new(bullet: &Bullet, config: &SimulationConfig) -> Self {
+        Self {
+            bullet: bullet.clone(),
+            velocity: bullet.velocity(config),
+        }
+    }
+    /// Where the two bullets meet during the tick, if they come within `INTERCEPT_RADIUS`
+    fn intercept(&self, other: &Self) -> Option<Point2D> {
+        let offset = self.bullet.position - other.bullet.position;
+        let velocity = self.velocity - other.velocity;
+        // closest approach is at time along / speed_2, clamped to the tick
+        let speed_2 = velocity.square_length().max(1);
+        let along = (-offset.dot(velocity)).max(0).min(speed_2);
+        let (num, den) = (along as i128, speed_2 as i128);
+        let dx = offset.x as i128 * den + velocity.x as i128 * num;
+        let dy = offset.y as i128 * den + velocity.y as i128 * num;
+        let radius = Self::INTERCEPT_RADIUS as i128 * den;
+        if dx * dx + dy * dy <= radius * radius {
+            Some(self.bullet.position + self.velocity * along / speed_2)
+        } else {
+            None
+        }
+    }
+}
+
+impl rstar::RTreeObject for BulletPath {
+    type Envelope = rstar::AABB<Point2D>;
+    fn envelope(&self) -> Self::Envelope {
+        let from = self.bullet.position;
+        let to = from + self.velocity;
+        let r = Vector2D::new(Self::INTERCEPT_RADIUS, Self::INTERCEPT_RADIUS);
+        rstar::AABB::from_corners(from.min(to) - r, from.max(to) + r)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Player {
     name: String,
+    /// `None` when playing every player for themselves
+    team: Option<u8>,
     input: Input,
+    /// When the player's next tank will spawn, `None` while they have one
+    respawn: Option<Time>,
+    stats: PlayerStats,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct PlayerStats {
+    pub kills: u32,
+    pub deaths: u32,
+    pub damage_dealt: i64,
+    pub shots_fired: u32,
+    pub shots_hit: u32,
+}
+
+impl PlayerStats {
+    /// Fraction of fired shots that hit a tank
+    pub fn accuracy(&self) -> f32 {
+        match self.shots_fired {
+            0 => 0.0,
+            fired => self.shots_hit as f32 / fired as f32,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct ScoreboardRow {
+    pub name: String,
+    pub stats: PlayerStats,
+}
+
+/// Players ordered by kills, then by fewest deaths
+#[derive(Clone, Debug)]
+pub struct Scoreboard {
+    pub rows: Vec<ScoreboardRow>,
 }
 
+/// Settings that can vary from match to match
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct GameRules {
+    /// Milliseconds between a tank being destroyed and its player respawning
+    pub respawn_delay: u32,
+    /// Whether bullets from different players shoot each other down
+    pub bullets_collide: bool,
+    /// Number of teams players are split between, 0 for free for all
+    pub teams: u8,
+    /// Whether bullets hurt tanks on the same team as the one that fired them
+    pub friendly_fire: bool,
+}
+
+impl Default for GameRules {
+    fn default() -> Self {
+        Self {
+            respawn_delay: 3000,
+            bullets_collide: false,
+            teams: 0,
+            friendly_fire: false,
+        }
+    }
+}
+
+/// Something that happened during a tick, for logging, effects and kill feeds
 #[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum GameEvent {
+    ShotFired {
+        player: Idx<'static, Player>,
+        position: Point2D,
+        angle: BinAngle,
+    },
+    BulletHit {
+        player: Idx<'static, Player>,
+        tank: Idx<'static, Tank>,
+        position: Point2D,
+        damage: i64,
+    },
+    TankKilled {
+        killer: Idx<'static, Player>,
+        victim: Idx<'static, Player>,
+        position: Point2D,
+    },
+    /// Carries the name since the event reaches clients ahead of a state with the player in it
+    PlayerJoined {
+        player: Idx<'static, Player>,
+        name: String,
+    },
+    PlayerLeft {
+        player: Idx<'static, Player>,
+    },
+    Respawned {
+        player: Idx<'static, Player>,
+        tank: Idx<'static, Tank>,
+        position: Point2D,
+    },
+    /// Two bullets destroyed each other, only with `GameRules::bullets_collide`
+    BulletsCollided {
+        position: Point2D,
+    },
+}
+
+impl GameEvent {
+    /// Where it happened, for events only players nearby need to hear about
+    ///
+    /// Kills, joins and leaves have none, everyone hears about those for the kill feed.
+    pub fn position(&self) -> Option<Point2D> {
+        match self {
+            GameEvent::ShotFired { position, .. }
+            | GameEvent::BulletHit { position, .. }
+            | GameEvent::Respawned { position, .. }
+            | GameEvent::BulletsCollided { position } => Some(*position),
+            GameEvent::TankKilled { .. }
+            | GameEvent::PlayerJoined { .. }
+            | GameEvent::PlayerLeft { .. } => None,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
 pub struct GameState {
+    /// Sent once when connecting rather than with every state
+    pub(crate) level: Arc<Level>,
+    /// Also sent once when connecting
+    pub(crate) config: Arc<SimulationConfig>,
+    pub(crate) rules: GameRules,
     pub(crate) players: StableList<Player>,
     pub(crate) tanks: StableList<Tank>,
     pub(crate) tank_bullets: StableList<Vec<Bullet>>,
     pub(crate) bullets: ElementList<Bullet>,
+    /// Built from the level and tanks, never sent
     collision: CollisionMap,
     time: Time,
+    /// Joins and leaves since the last tick, reported by the next one
+    pending_events: Vec<GameEvent>,
 }
 
-#[derive(Copy, Clone, Debug, Serialize, Deserialize)]
+/// The part of a [`GameState`] that is sent every tick
+///
+/// Everything else is either sent once when connecting or rebuilt from this by
+/// [`GameState::from_snapshot`].
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Snapshot {
+    pub(crate) players: StableList<Player>,
+    pub(crate) tanks: StableList<Tank>,
+    pub(crate) tank_bullets: StableList<Vec<Bullet>>,
+    pub(crate) bullets: ElementList<Bullet>,
+    time: Time,
+    /// Whether tanks and bullets far from the receiving player were left out, see
+    /// [`GameState::snapshot_around`]
+    pub(crate) culled: bool,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct Time(pub u64);
 
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 enum Hitbox {
     Tank(TankHitbox, Idx<'static, Tank>),
+    Wall(Wall),
 }
 
-#[derive(Clone, Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug)]
 struct CollisionMap {
-    rtree: rstar::RTree<Hitbox>,
+    /// Never changes once the level is loaded, so states share it rather than copying it
+    walls: Arc<rstar::RTree<Hitbox>>,
+    /// Updated in place as tanks move, spawn and die
+    tanks: rstar::RTree<Hitbox>,
+    /// Every bullet in the air, updated in place as bullets move, are fired and are spent
+    bullets: rstar::RTree<BulletPath>,
+}
+
+impl Hitbox {
+    fn polygon(&self) -> [Point2D; 4] {
+        match &self {
+            Self::Tank(hitbox, _) => hitbox.corners(),
+            Self::Wall(wall) => wall.corners(),
+        }
+    }
+    /// Slot of the tank this belongs to, `None` for walls
+    fn tank_index(&self) -> Option<usize> {
+        match &self {
+            Self::Tank(_, idx) => Some(idx.0),
+            Self::Wall(_) => None,
+        }
+    }
 }
 
 impl rstar::RTreeObject for Hitbox {
@@ -391,6 +988,7 @@ impl rstar::RTreeObject for Hitbox {
     fn envelope(&self) -> Self::Envelope {
         match &self {
             Self::Tank(hitbox, idx) => hitbox.envelope(),
+            Self::Wall(wall) => wall.envelope(),
         }
     }
 }
@@ -398,167 +996,928 @@ impl rstar::PointDistance for Hitbox {
     fn distance_2(&self, point: &Point2D) -> i64 {
         match &self {
             Self::Tank(hitbox, idx) => hitbox.distance_2(point),
+            Self::Wall(wall) => wall.distance_2(point),
         }
     }
     fn contains_point(&self, point: &Point2D) -> bool {
         match &self {
             Self::Tank(hitbox, idx) => hitbox.contains_point(point),
+            Self::Wall(wall) => wall.contains_point(point),
         }
     }
     fn distance_2_if_less_or_equal(&self, point: &Point2D, max_distance_2: i64) -> Option<i64> {
         match &self {
             Self::Tank(hitbox, idx) => hitbox.distance_2_if_less_or_equal(point, max_distance_2),
+            Self::Wall(wall) => wall.distance_2_if_less_or_equal(point, max_distance_2),
         }
     }
 }
 
 impl CollisionMap {
     fn add(&mut self, element: Hitbox) {
-        self.rtree.insert(element);
+        self.tanks.insert(element);
     }
     fn remove(&mut self, element: Hitbox) -> Option<Hitbox> {
-        self.rtree.remove(&element)
+        self.tanks.remove(&element)
     }
-    fn collide(&self, position: Point2D) -> Option<&Hitbox> {
-        self.rtree.locate_at_point(&position)
+    /// Every wall and tank whose envelope intersects `envelope`
+    fn intersecting(&self, envelope: rstar::AABB<Point2D>) -> impl Iterator<Item = &Hitbox> + '_ {
+        self.walls
+            .locate_in_envelope_intersecting(&envelope)
+            .chain(self.tanks.locate_in_envelope_intersecting(&envelope))
+    }
+    /// First hitbox along the segment `from`..`to`, and the point where it is entered
+    fn sweep(&self, from: Point2D, to: Point2D) -> Option<(&Hitbox, Point2D)> {
+        let mut first: Option<((i64, i64), &Hitbox)> = None;
+        for h in self.intersecting(rstar::AABB::from_corners(from, to)) {
+            if let Some(t) = segment_entry(from, to, &h.polygon()) {
+                // ties go to the lowest tank index rather than whichever the tree gave us first,
+                // so a rebuilt tree gives the same answer
+                let earlier = |(first_t, first_h): ((i64, i64), &Hitbox)| {
+                    fraction_lt(t, first_t)
+                        || (!fraction_lt(first_t, t) && h.tank_index() < first_h.tank_index())
+                };
+                if first.map(earlier).unwrap_or(true) {
+                    first = Some((t, h));
+                }
+            }
+        }
+        let ((num, den), h) = first?;
+        let direction = to - from;
+        let along = |d: i64| (d as i128 * num as i128 / den as i128) as i64;
+        Some((
+            h,
+            from + Vector2D::new(along(direction.x), along(direction.y)),
+        ))
+    }
+    /// Every tank with part of its hitbox within `radius` of `position`, in index order
+    fn tanks_within(&self, position: Point2D, radius: i64) -> Vec<Idx<'static, Tank>> {
+        let mut tanks: Vec<_> = self
+            .tanks
+            .locate_within_distance(position, radius * radius)
+            .filter_map(|h| match h {
+                Hitbox::Tank(_, idx) => Some(*idx),
+                Hitbox::Wall(_) => None,
+            })
+            .collect();
+        tanks.sort_by_key(|idx| idx.0);
+        tanks
+    }
+    /// Total translation needed to push `hitbox` out of everything it overlaps, ignoring the
+    /// hitbox belonging to `tank` itself
+    fn penetration(&self, hitbox: &TankHitbox, tank: Idx<'static, Tank>) -> Option<Vector2D> {
+        let corners = hitbox.corners();
+        self.intersecting(rstar::RTreeObject::envelope(hitbox))
+            .filter(|h| match h {
+                Hitbox::Tank(_, idx) => *idx != tank,
+                Hitbox::Wall(_) => true,
+            })
+            .filter_map(|h| separate(&corners, &h.polygon()))
+            .fold(None, |total, push| {
+                Some(total.unwrap_or_else(Vector2D::zero) + push)
+            })
     }
-    fn new() -> Self {
+    fn new(walls: Vec<Hitbox>) -> Self {
         CollisionMap {
-            rtree: rstar::RTree::new(),
+            walls: Arc::new(rstar::RTree::bulk_load(walls)),
+            tanks: rstar::RTree::new(),
+            bullets: rstar::RTree::new(),
         }
     }
+    fn add_bullet(&mut self, bullet: &Bullet, config: &SimulationConfig) {
+        self.bullets.insert(BulletPath::new(bullet, config));
+    }
+    fn remove_bullet(&mut self, bullet: &Bullet, config: &SimulationConfig) {
+        self.bullets.remove(&BulletPath::new(bullet, config));
+    }
+    /// Bullets whose paths this tick come close enough to `path` that they may cross it
+    fn bullets_near<'a>(&'a self, path: &BulletPath) -> impl Iterator<Item = &'a BulletPath> {
+        self.bullets
+            .locate_in_envelope_intersecting(&rstar::RTreeObject::envelope(path))
+    }
+    /// Every bullet within `radius` of `position`, ordered by [`BulletKey`]
+    fn bullets_within(&self, position: Point2D, radius: i64) -> Vec<&Bullet> {
+        let r = Vector2D::new(radius, radius);
+        let mut bullets: Vec<_> = self
+            .bullets
+            .locate_in_envelope_intersecting(&rstar::AABB::from_corners(position - r, position + r))
+            .map(|path| &path.bullet)
+            .filter(|bullet| (bullet.position - position).square_length() <= radius * radius)
+            .collect();
+        bullets.sort_by_key(|bullet| bullet.key());
+        bullets
+    }
+    /// Replaces the tank hitboxes with ones built from `tanks`
+    fn rebuild_tanks(&mut self, tanks: &StableList<Tank>, config: &SimulationConfig) {
+        self.tanks = rstar::RTree::bulk_load(
+            tanks
+                .into_iter()
+                .filter_map(|(idx, tank)| tank.map(|tank| Hitbox::Tank(tank.hitbox(config), idx)))
+                .collect(),
+        );
+    }
+    /// Replaces the bullet paths with ones built from `bullets`
+    fn rebuild_bullets(&mut self, bullets: &[Bullet], config: &SimulationConfig) {
+        self.bullets = rstar::RTree::bulk_load(
+            bullets
+                .iter()
+                .map(|bullet| BulletPath::new(bullet, config))
+                .collect(),
+        );
+    }
 }
 
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 enum Collision {
     Tank(Idx<'static, Tank>),
-    Arena,
+    Wall(Wall),
 }
 
 impl GameState {
     pub fn new() -> Self {
         Self {
+            level: Default::default(),
+            config: Default::default(),
+            rules: Default::default(),
             players: StableList::from(vec![]),
             tanks: StableList::from(vec![]),
             tank_bullets: StableList::from(vec![]),
             bullets: ElementList::from(vec![]),
-            collision: CollisionMap::new(),
+            collision: CollisionMap::new(vec![]),
             time: Time(0),
+            pending_events: vec![],
         }
     }
-    pub fn tick(&self) -> Self {
-        let mut new_players = self.players.clone();
-        let mut new_tanks = self.tanks.clone();
-        let mut new_bullets = Vec::with_capacity(self.bullets.len());
-        let mut collision = self.collision.clone();
-        let mut removed_tanks = vec![];
-        let mut moved_tanks = vec![];
+    pub fn with_level(level: Arc<Level>, config: Arc<SimulationConfig>, rules: GameRules) -> Self {
+        let collision = CollisionMap::new(
+            level
+                .walls
+                .into_iter()
+                .map(|(_, wall)| Hitbox::Wall(wall.clone()))
+                .collect(),
+        );
+        Self {
+            level,
+            config,
+            rules,
+            collision,
+            ..Self::new()
+        }
+    }
+    /// Rebuilds a state from one received from the server
+    pub fn from_snapshot(
+        snapshot: Snapshot,
+        level: Arc<Level>,
+        config: Arc<SimulationConfig>,
+        rules: GameRules,
+    ) -> Self {
+        let mut state = Self::with_level(level, config, rules);
+        state.players = snapshot.players;
+        state.tanks = snapshot.tanks;
+        state.tank_bullets = snapshot.tank_bullets;
+        state.bullets = snapshot.bullets;
+        state.time = snapshot.time;
+        state.collision.rebuild_tanks(&state.tanks, &state.config);
+        state
+            .collision
+            .rebuild_bullets(&state.bullets.list, &state.config);
+        state
+    }
+    /// A copy of the part of the state that is sent every tick
+    pub fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            players: self.players.clone(),
+            tanks: self.tanks.clone(),
+            tank_bullets: self.tank_bullets.clone(),
+            bullets: self.bullets.clone(),
+            time: self.time,
+            culled: false,
+        }
+    }
+    /// A snapshot with only the tanks and bullets within `radius` of `center`
+    ///
+    /// Every player is still there, so the scoreboard stays complete.
+    pub fn snapshot_around(&self, center: Point2D, radius: i64) -> Snapshot {
+        let mut visible = vec![false; self.tanks.len()];
+        for tank in self.collision.tanks_within(center, radius) {
+            visible[tank.0] = true;
+        }
+        let visible = |i: usize| visible.get(i) == Some(&true);
+        let bullets = self
+            .collision
+            .bullets_within(center, radius)
+            .into_iter()
+            .cloned()
+            .collect::<Vec<_>>();
+        Snapshot {
+            players: self.players.clone(),
+            tanks: self.tanks.filtered(visible),
+            tank_bullets: self.tank_bullets.filtered(visible),
+            bullets: bullets.into(),
+            time: self.time,
+            culled: true,
+        }
+    }
+    /// Adds a player to the smallest team, they get a tank on the next tick
+    pub fn add_player(&mut self, name: String) -> Idx<'static, Player> {
+        let team = self.smallest_team();
+        let player = self.players.push(Player {
+            name: name.clone(),
+            team,
+            input: Default::default(),
+            respawn: Some(self.time),
+            stats: Default::default(),
+        });
+        self.pending_events
+            .push(GameEvent::PlayerJoined { player, name });
+        player
+    }
+    /// Removes a player along with their tank, its pending hits and its hitbox
+    ///
+    /// Bullets they already fired stay in the air.
+    pub fn remove_player(&mut self, player: &Idx<'static, Player>) -> Option<Player> {
+        let removed = self.players.remove(player);
+        if let Some(tank_idx) = self.tank_of(*player) {
+            let tank = self.tanks.remove(&tank_idx).unwrap();
+            self.collision
+                .remove(Hitbox::Tank(tank.hitbox(&self.config), tank_idx));
+            if let Some(bullets) = self.tank_bullets.list.get_mut(tank_idx.0) {
+                *bullets = None;
+            }
+        }
+        if removed.is_some() {
+            self.pending_events
+                .push(GameEvent::PlayerLeft { player: *player });
+        }
+        removed
+    }
+    /// The tank belonging to `player`, if they have one
+    pub fn tank_of(&self, player: Idx<'static, Player>) -> Option<Idx<'static, Tank>> {
+        self.tanks
+            .into_iter()
+            .find(|(_, tank)| tank.map(|t| t.player == player).unwrap_or(false))
+            .map(|(idx, _)| idx)
+    }
+    /// The team with the fewest players, `None` if there are no teams
+    fn smallest_team(&self) -> Option<u8> {
+        (0..self.rules.teams).min_by_key(|team| {
+            self.players
+                .into_iter()
+                .filter(|(_, player)| player.map(|p| p.team == Some(*team)).unwrap_or(false))
+                .count()
+        })
+    }
+    /// Picks the spawn point for the player's team furthest from any living enemy tank,
+    /// preferring ones no teammate is standing on
+    ///
+    /// A spawn point without a team belongs to the team whose zone it's in. When the map has none
+    /// for their team the player spawns in the middle of their team's zone, or failing that at
+    /// any spawn point.
+    fn spawn_point(
+        &self,
+        player: Idx<'static, Player>,
+        tanks: &StableList<Tank>,
+    ) -> (Point2D, BinAngle) {
+        let team = self.players[player].as_ref().and_then(|p| p.team);
+        let zones = &self.level.team_zones;
+        let ours = |spawn: &&map::SpawnPoint| {
+            let owner = spawn.team.or_else(|| {
+                (zones.iter())
+                    .find(|(_, area)| area.contains(spawn.position))
+                    .map(|(team, _)| *team)
+            });
+            team.is_none() || owner.is_none() || owner == team
+        };
+        let zone_spawn = (zones.iter())
+            .find(|(zone, _)| Some(*zone) == team)
+            .map(|(_, area)| {
+                // facing the middle of all the zones, towards the other teams
+                let middle = (zones.iter())
+                    .map(|(_, area)| area.center().to_vector())
+                    .fold(Vector2D::zero(), |sum, center| sum + center)
+                    / zones.len() as i64;
+                map::SpawnPoint {
+                    position: area.center(),
+                    angle: BinAngle::of_vector(middle - area.center().to_vector()),
+                    team,
+                }
+            });
+        let spawns = match self.level.spawns.iter().any(|spawn| ours(&spawn)) {
+            true => self.level.spawns.iter().filter(ours).collect::<Vec<_>>(),
+            false => match &zone_spawn {
+                Some(spawn) => vec![spawn],
+                None => self.level.spawns.iter().collect(),
+            },
+        };
+        let others = || {
+            tanks
+                .into_iter()
+                .filter_map(|(_, tank)| tank)
+                .filter(|tank| tank.player != player)
+        };
+        let clearance = 4 * self.config.tank_size * GM_ONE_PIXEL;
+        spawns
+            .into_iter()
+            .max_by_key(|spawn| {
+                let distance = |tank: &Tank| (tank.position - spawn.position).square_length();
+                let free = others().all(|tank| distance(tank) > clearance * clearance);
+                let enemy_distance = others()
+                    .filter(|tank| team.is_none() || tank.team != team)
+                    .map(distance)
+                    .min()
+                    .unwrap_or(i64::MAX);
+                (free, enemy_distance)
+            })
+            .map(|spawn| (spawn.position, spawn.angle))
+            .unwrap_or((Point2D::zero(), BinAngle::zero()))
+    }
+    pub fn tick(&self) -> (Self, Vec<GameEvent>) {
+        let mut state = self.clone();
+        let events = state.advance();
+        (state, events)
+    }
+    /// Steps the state forward a tick in place
+    ///
+    /// Does the same as [`GameState::tick`], but only touches what changes rather than building
+    /// a whole new state.
+    pub fn advance(&mut self) -> Vec<GameEvent> {
+        let mut events = mem::take(&mut self.pending_events);
+        let config = self.config.clone();
         // tick objects
-        let tank_updates: Vec<_> = self
+        let tanks: Vec<_> = self
             .tanks
             .into_iter()
             .zip(&self.tank_bullets)
-            .filter_map(|((tank_idx, tank), (b_idx, b))| {
-                tank.map(|tank| ((tank_idx, tank), (b_idx, b)))
-            })
-            .map(|((tank_idx, tank), (_, bullets))| {
-                (
-                    tank_idx,
-                    tank.tick(&self, bullets.map(|b| b.as_ref()).unwrap_or(&[])),
-                )
+            .filter_map(|((tank_idx, tank), (_, bullets))| {
+                tank.map(|tank| (tank_idx, tank, bullets.map(|b| &b[..]).unwrap_or(&[])))
             })
             .collect();
-        let bullet_updates: Vec<_> = self
-            .bullets
+        let tank_updates = map_in_order(&tanks, |(tank_idx, tank, bullets)| {
+            (*tank_idx, tank.tick(*tank_idx, &self, bullets))
+        });
+        let bullets: Vec<_> = self.bullets.into_iter().collect();
+        let mut bullet_updates = map_in_order(&bullets, |(i, bullet)| (*i, bullet.tick(&self)));
+        let intercepts = match self.rules.bullets_collide {
+            true => self.intercepts(),
+            false => vec![],
+        };
+        let respawning: Vec<_> = self
+            .players
             .into_iter()
-            .map(|(i, bullet)| (i, bullet.tick(&self)))
+            .filter(|(_, player)| match player.and_then(|p| p.respawn) {
+                Some(time) => time.0 <= self.time.0,
+                None => false,
+            })
+            .map(|(idx, _)| idx)
             .collect();
 
-        // process updates
+        // process updates, keeping the old hitboxes of tanks that moved or died so they can be
+        // taken out of the collision map once nothing needs it any more
+        let mut removed_tanks = vec![];
+        let mut moved_tanks = vec![];
+        let mut new_bullets = Vec::with_capacity(self.bullets.len());
+        let teams: Vec<_> = (self.tanks.list.iter())
+            .map(|tank| tank.as_ref().map(|tank| tank.team))
+            .collect();
         for (tank_idx, update) in tank_updates {
+            let old_hitbox = self.tanks[tank_idx].as_ref().unwrap().hitbox(&config);
             match update {
                 TankUpdate::Dead(player) => {
-                    let tank = self.tanks[tank_idx].as_ref().unwrap();
-                    new_tanks[tank_idx] = None;
-                    removed_tanks.push(tank_idx);
-                    println!(
-                        "PLAYER {:?} KILLED {:?}'S TANK",
-                        self.players[player], self.players[tank.player]
-                    );
+                    let tank = self.tanks.remove(&tank_idx).unwrap();
+                    removed_tanks.push((tank_idx, old_hitbox));
+                    if let Some(victim) = self.players.get_mut(tank.player) {
+                        let delay = config.ticks(self.rules.respawn_delay) as u64;
+                        victim.respawn = Some(Time(self.time.0.wrapping_add(delay)));
+                        victim.stats.deaths += 1;
+                    }
+                    if player != tank.player {
+                        // no credit for taking out a teammate
+                        if let Some(killer) = self.players.get_mut(player) {
+                            if killer.team.is_none() || killer.team != tank.team {
+                                killer.stats.kills += 1;
+                            }
+                        }
+                    }
+                    events.push(GameEvent::TankKilled {
+                        killer: player,
+                        victim: tank.player,
+                        position: tank.position,
+                    });
+                }
+                TankUpdate::Removed => {
+                    self.tanks.remove(&tank_idx);
+                    removed_tanks.push((tank_idx, old_hitbox));
                 }
                 TankUpdate::Alive(tank) => {
-                    if tank.hitbox() != self.tanks[tank_idx].as_ref().unwrap().hitbox() {
-                        moved_tanks.push(tank_idx);
+                    if tank.hitbox(&config) != old_hitbox {
+                        moved_tanks.push((tank_idx, old_hitbox));
                     }
-                    new_tanks[tank_idx] = Some(tank);
+                    self.tanks[tank_idx] = Some(tank);
                 }
-                TankUpdate::Fire(tank, bullet) => {
-                    if tank.hitbox() != self.tanks[tank_idx].as_ref().unwrap().hitbox() {
-                        moved_tanks.push(tank_idx);
+                TankUpdate::Fire(tank, bullets) => {
+                    if tank.hitbox(&config) != old_hitbox {
+                        moved_tanks.push((tank_idx, old_hitbox));
                     }
-                    new_tanks[tank_idx] = Some(tank);
-                    new_bullets.push(bullet);
+                    for bullet in bullets {
+                        if let Some(player) = self.players[tank.player].as_mut() {
+                            player.stats.shots_fired += 1;
+                        }
+                        events.push(GameEvent::ShotFired {
+                            player: tank.player,
+                            position: bullet.position,
+                            angle: bullet.angle,
+                        });
+                        self.collision.add_bullet(&bullet, &config);
+                        new_bullets.push(bullet);
+                    }
+                    self.tanks[tank_idx] = Some(tank);
                 }
             }
         }
 
-        let mut new_tank_bullets: Vec<Option<Vec<_>>> = vec![None; new_tanks.len()];
+        for (a, b, position) in intercepts {
+            bullet_updates[a].1 = BulletUpdate::Dead;
+            bullet_updates[b].1 = BulletUpdate::Dead;
+            events.push(GameEvent::BulletsCollided { position });
+        }
+        let mut new_tank_bullets: Vec<Option<Vec<_>>> = vec![None; self.tanks.len()];
+        let mut dead = vec![false; self.tanks.len()];
+        for (tank_idx, _) in &removed_tanks {
+            dead[tank_idx.0] = true;
+        }
+        let friendly_fire = self.rules.friendly_fire;
+        // bullets that reach a tank destroyed this tick, or a teammate without friendly fire, are
+        // spent without doing anything. Dead tanks are still in the collision map until the end
+        // of the tick, so they're looked up by the teams from before it
+        let harms = |bullet: &Bullet, tank: Idx<'static, Tank>| {
+            !dead[tank.0]
+                && (friendly_fire || bullet.team.is_none() || teams[tank.0] != Some(bullet.team))
+        };
+        let players = &mut self.players;
+        // splash damage counts towards damage dealt but not towards shots hit
+        let mut hit = |bullet: Bullet, tank: Idx<'static, Tank>, position: Point2D, direct| {
+            if let Some(player) = players.get_mut(bullet.player) {
+                player.stats.shots_hit += direct as u32;
+                player.stats.damage_dealt += bullet.damage;
+            }
+            events.push(GameEvent::BulletHit {
+                player: bullet.player,
+                tank,
+                position,
+                damage: bullet.damage,
+            });
+            new_tank_bullets[tank.0]
+                .get_or_insert_with(Vec::new)
+                .push(bullet);
+        };
         for (idx, update) in bullet_updates {
-            match update {
-                BulletUpdate::Hit(tank) => match &mut new_tank_bullets[tank.0] {
-                    &mut Some(ref mut v) => {
-                        v.push(self.bullets[idx].clone());
+            let bullet = &self.bullets[idx];
+            if !matches!(&update, BulletUpdate::Move(moved) if moved == bullet) {
+                self.collision.remove_bullet(bullet, &config);
+            }
+            let (direct, position) = match update {
+                BulletUpdate::Hit(tank, position) => (Some(tank), position),
+                BulletUpdate::Impact(position) => (None, position),
+                BulletUpdate::Move(moved) => {
+                    if moved != *bullet {
+                        self.collision.add_bullet(&moved, &config);
                     }
-                    x @ &mut None => {
-                        *x = Some(vec![self.bullets[idx].clone()]);
+                    new_bullets.push(moved);
+                    continue;
+                }
+                BulletUpdate::Dead => continue,
+            };
+            if let Some(tank) = direct.filter(|tank| harms(bullet, *tank)) {
+                hit(bullet.clone(), tank, position, true);
+            }
+            let spec = config.weapon(bullet.kind);
+            if spec.splash_radius > 0 {
+                let radius = spec.splash_radius * GM_ONE_PIXEL;
+                for tank in self.collision.tanks_within(position, radius) {
+                    if Some(tank) != direct && harms(bullet, tank) {
+                        let splash = Bullet {
+                            damage: spec.splash_damage,
+                            ..bullet.clone()
+                        };
+                        hit(splash, tank, position, false);
                     }
-                },
-                BulletUpdate::Move(bullet) => new_bullets.push(bullet),
-                BulletUpdate::Dead => {
-                    // do nothing
                 }
             }
         }
+        self.bullets = new_bullets.into();
+
+        // respawn players whose timer has run out
+        let mut spawned_tanks = vec![];
+        for player_idx in respawning {
+            let (position, angle) = self.spawn_point(player_idx, &self.tanks);
+            let player = self.players[player_idx].as_mut().unwrap();
+            player.respawn = None;
+            let tank = Tank::spawn(player_idx, player.team, position, angle, &config);
+            let tank_idx = self.tanks.push(tank);
+            events.push(GameEvent::Respawned {
+                player: player_idx,
+                tank: tank_idx,
+                position,
+            });
+            spawned_tanks.push(tank_idx);
+        }
+        // hits on tanks that died this tick go nowhere, and a respawned tank may reuse the slot
+        // of one that died, don't let it inherit the hits meant for the old one
+        new_tank_bullets.resize(self.tanks.len(), None);
+        for tank_idx in removed_tanks
+            .iter()
+            .map(|(idx, _)| idx)
+            .chain(&spawned_tanks)
+        {
+            new_tank_bullets[tank_idx.0] = None;
+        }
+        self.tank_bullets = new_tank_bullets.into();
 
         // reduce
-        for tank in removed_tanks {
-            let a = collision.remove(Hitbox::Tank(
-                self.tanks[tank].as_ref().unwrap().hitbox(),
-                tank,
-            ));
-        }
-        for tank_idx in moved_tanks {
-            let a = collision.remove(Hitbox::Tank(
-                self.tanks[tank_idx].as_ref().unwrap().hitbox(),
-                tank_idx,
-            ));
-            collision.add(Hitbox::Tank(
-                new_tanks[tank_idx].as_ref().unwrap().hitbox(),
-                tank_idx,
-            ));
+        for (tank_idx, hitbox) in removed_tanks {
+            self.collision.remove(Hitbox::Tank(hitbox, tank_idx));
         }
-        Self {
-            players: new_players,
-            tanks: new_tanks,
-            tank_bullets: new_tank_bullets.into(),
-            bullets: ElementList { list: new_bullets },
-            collision,
-            time: Time(self.time.0.wrapping_add(1)),
+        for (tank_idx, hitbox) in moved_tanks {
+            self.collision.remove(Hitbox::Tank(hitbox, tank_idx));
+            let tank = self.tanks[tank_idx].as_ref().unwrap();
+            self.collision
+                .add(Hitbox::Tank(tank.hitbox(&config), tank_idx));
         }
+        for tank_idx in spawned_tanks {
+            let tank = self.tanks[tank_idx].as_ref().unwrap();
+            self.collision
+                .add(Hitbox::Tank(tank.hitbox(&config), tank_idx));
+        }
+        self.time = Time(self.time.0.wrapping_add(1));
+        events
     }
-    fn collide(&self, position: Point2D) -> Option<Collision> {
-        if let Some(h) = self.collision.collide(position).cloned() {
-            Some(match h {
-                Hitbox::Tank(h, tank_idx) => Collision::Tank(tank_idx),
+    pub fn scoreboard(&self) -> Scoreboard {
+        let mut rows: Vec<_> = self
+            .players
+            .into_iter()
+            .filter_map(|(_, player)| player)
+            .map(|player| ScoreboardRow {
+                name: player.name.clone(),
+                stats: player.stats.clone(),
             })
+            .collect();
+        rows.sort_by_key(|row| (std::cmp::Reverse(row.stats.kills), row.stats.deaths));
+        Scoreboard { rows }
+    }
+    /// Pairs of bullets from different players that meet this tick, and where they meet
+    ///
+    /// Each bullet is only part of one pair.
+    fn intercepts(&self) -> Vec<(usize, usize, Point2D)> {
+        let positions: HashMap<_, _> = self
+            .bullets
+            .list
+            .iter()
+            .enumerate()
+            .map(|(idx, bullet)| (bullet.key(), idx))
+            .collect();
+        let mut destroyed = vec![false; self.bullets.len()];
+        let mut intercepts = vec![];
+        for (idx, bullet) in self.bullets.list.iter().enumerate() {
+            if destroyed[idx] {
+                continue;
+            }
+            let path = BulletPath::new(bullet, &self.config);
+            // the first in the list rather than whichever the tree gives us first, so a rebuilt
+            // tree gives the same answer
+            let hit = self
+                .collision
+                .bullets_near(&path)
+                .filter(|other| other.bullet.player != bullet.player)
+                .filter_map(|other| {
+                    let other_idx = *positions.get(&other.bullet.key())?;
+                    match destroyed[other_idx] {
+                        true => None,
+                        false => path.intercept(other).map(|position| (other_idx, position)),
+                    }
+                })
+                .min_by_key(|(other_idx, _)| *other_idx);
+            if let Some((other, position)) = hit {
+                destroyed[idx] = true;
+                destroyed[other] = true;
+                intercepts.push((idx, other, position));
+            }
+        }
+        intercepts
+    }
+    /// First thing hit moving from `from` to `to`, and where it was hit
+    fn sweep(&self, from: Point2D, to: Point2D) -> Option<(Collision, Point2D)> {
+        if let Some((h, position)) = self.collision.sweep(from, to) {
+            let collision = match h {
+                Hitbox::Tank(h, tank_idx) => Collision::Tank(*tank_idx),
+                Hitbox::Wall(wall) => Collision::Wall(wall.clone()),
+            };
+            Some((collision, position))
         } else {
             None
         }
     }
 }
 
+/// Maps every item, keeping the results in the same order as `items`
+///
+/// Spread across threads with the `parallel` feature. The order of the results never depends on
+/// how the work was split, so the outcome of a tick doesn't either.
+fn map_in_order<T: Sync, U: Send>(items: &[T], f: impl Fn(&T) -> U + Sync + Send) -> Vec<U> {
+    #[cfg(feature = "parallel")]
+    {
+        use rayon::prelude::*;
+        items.par_iter().map(f).collect()
+    }
+    #[cfg(not(feature = "parallel"))]
+    {
+        items.iter().map(f).collect()
+    }
+}
+
+/// The value given after the flag `name` on the command line, if the flag is there
+///
+/// Panics if the flag is the last argument or its value doesn't parse, rather than carrying on
+/// without it.
+fn flag_value<T>(args: &[String], name: &str) -> Option<T>
+where
+    T: std::str::FromStr,
+    T::Err: std::fmt::Display,
+{
+    let flag = args.iter().position(|arg| arg == name)?;
+    let value = args
+        .get(flag + 1)
+        .unwrap_or_else(|| panic!("{} needs a value after it", name));
+    match value.parse() {
+        Ok(value) => Some(value),
+        Err(e) => panic!("Bad value {:?} for {}: {}", value, name, e),
+    }
+}
+
 enum BulletUpdate {
-    Hit(Idx<'static, Tank>), // hit tank
-    Move(Bullet),            // otherwise move forward
+    Hit(Idx<'static, Tank>, Point2D), // hit tank
+    Impact(Point2D),                  // hit a wall
+    Move(Bullet),                     // otherwise move forward
     Dead,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Checks that players, tanks, pending hits and hitboxes all agree with each other
+    fn assert_consistent(state: &GameState) {
+        assert_eq!(state.tanks.len(), state.tank_bullets.len());
+        let mut owners = vec![];
+        for (idx, tank) in &state.tanks {
+            match tank {
+                Some(tank) => {
+                    assert!(state.players[tank.player].is_some(), "orphaned {:?}", idx);
+                    assert!(!owners.contains(&tank.player), "two tanks for one player");
+                    owners.push(tank.player);
+                    let hitbox = Hitbox::Tank(tank.hitbox(&state.config), idx);
+                    assert!(state.collision.tanks.iter().any(|h| *h == hitbox));
+                }
+                None => assert!(state.tank_bullets.list[idx.0].is_none()),
+            }
+        }
+        assert_eq!(state.collision.tanks.size(), owners.len());
+        assert_eq!(state.collision.bullets.size(), state.bullets.len());
+        for bullet in &state.bullets.list {
+            let path = BulletPath::new(bullet, &state.config);
+            assert!(state.collision.bullets.iter().any(|p| *p == path));
+        }
+    }
+
+    fn arena() -> GameState {
+        let map = Map::empty("churn", Size2D::new(800, 800));
+        GameState::with_level(
+            Arc::new(Level::from(&map)),
+            Default::default(),
+            Default::default(),
+        )
+    }
+
+    #[test]
+    fn default_config_is_valid() {
+        assert_eq!(SimulationConfig::default().validate(), Ok(()));
+    }
+
+    #[test]
+    fn flag_values() {
+        let args: Vec<String> = vec!["--tick-rate".into(), "30".into(), "--teams".into()];
+        assert_eq!(flag_value(&args, "--tick-rate"), Some(30));
+        assert_eq!(flag_value::<u8>(&args, "--view-radius"), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "--teams needs a value")]
+    fn flag_without_value() {
+        let args: Vec<String> = vec!["--tick-rate".into(), "30".into(), "--teams".into()];
+        flag_value::<u8>(&args, "--teams");
+    }
+
+    #[test]
+    fn rules_are_hashed() {
+        let state = arena();
+        let mut other = state.clone();
+        other.rules.friendly_fire = !state.rules.friendly_fire;
+        assert_ne!(state.content_hash(), other.content_hash());
+        assert_eq!(state.first_difference(&other), Some(Entity::Rules));
+    }
+
+    #[test]
+    fn leaving_removes_tank() {
+        let mut state = arena();
+        let player = state.add_player("a".to_owned());
+        state = state.tick().0;
+        let tank = state.tank_of(player).unwrap();
+        assert!(state.remove_player(&player).is_some());
+        assert!(state.tanks[tank].is_none());
+        assert!(state.tank_of(player).is_none());
+        assert_consistent(&state);
+        let (state, _) = state.tick();
+        assert_consistent(&state);
+    }
+
+    #[test]
+    fn rejoining_player_does_not_inherit_stale_handles() {
+        let mut state = arena();
+        let old = state.add_player("a".to_owned());
+        state = state.tick().0;
+        state.remove_player(&old);
+        let new = state.add_player("b".to_owned());
+        assert_eq!(old.0, new.0);
+        assert!(state.players[old].is_none());
+        assert!(state.remove_player(&old).is_none());
+        assert!(state.players[new].is_some());
+        state = state.tick().0;
+        assert!(state.tank_of(new).is_some());
+        assert_consistent(&state);
+    }
+
+    #[test]
+    fn join_leave_churn() {
+        let mut state = arena();
+        let mut players = vec![];
+        // fixed seed so failures are reproducible
+        let mut seed = 0x2545_f491u32;
+        let mut random = move |n: usize| {
+            seed ^= seed << 13;
+            seed ^= seed >> 17;
+            seed ^= seed << 5;
+            seed as usize % n
+        };
+        for _ in 0..600 {
+            match random(8) {
+                0 | 1 => players.push(state.add_player("churn".to_owned())),
+                2 if !players.is_empty() => {
+                    let player = players.swap_remove(random(players.len()));
+                    assert!(state.remove_player(&player).is_some());
+                }
+                _ => {}
+            }
+            for player in &players {
+                state.players[*player].as_mut().unwrap().input = Input {
+                    fire: true,
+                    drive: Some(Drive::Forward),
+                    rotate: Some(Turn::Left),
+                    turret: Some(Turn::Right),
+                    ..Default::default()
+                };
+            }
+            assert_consistent(&state);
+            state = state.tick().0;
+            assert_consistent(&state);
+        }
+    }
+
+    #[test]
+    fn teams_spawn_in_their_zones() {
+        let mut map = Map::empty("zones", Size2D::new(800, 800));
+        let spawn = |x| map::MapSpawn {
+            position: Point2D::new(x, 400),
+            angle: 0.0,
+            team: None,
+        };
+        map.spawns = vec![spawn(100), spawn(700)];
+        let zone = |team, min_x, max_x| map::TeamZone {
+            team,
+            area: Box2D::new(Point2D::new(min_x, 0), Point2D::new(max_x, 800)),
+        };
+        // the last team has no spawn point in its zone
+        map.team_zones = vec![zone(0, 0, 200), zone(1, 600, 800), zone(2, 300, 500)];
+        let rules = GameRules {
+            teams: 3,
+            ..Default::default()
+        };
+        let mut state =
+            GameState::with_level(Arc::new(Level::from(&map)), Default::default(), rules);
+        let players: Vec<_> = (0..6)
+            .map(|_| state.add_player("zoned".to_owned()))
+            .collect();
+        state = state.tick().0;
+        for player in players {
+            let team = state.players[player].as_ref().unwrap().team.unwrap();
+            let tank = state.tanks[state.tank_of(player).unwrap()]
+                .as_ref()
+                .unwrap();
+            let (_, area) = state.level.team_zones[team as usize];
+            assert!(
+                area.contains(tank.position),
+                "team {} outside its zone",
+                team
+            );
+        }
+    }
+
+    #[test]
+    fn no_hits_on_tanks_dying_this_tick() {
+        let mut state = arena();
+        let victim = state.add_player("b".to_owned());
+        state = state.tick().0;
+        // without a tank of their own in the way
+        let shooter = state.add_player("a".to_owned());
+        let tank = state.tank_of(victim).unwrap();
+        let position = state.tanks[tank].as_ref().unwrap().position;
+        let config = state.config.clone();
+        // last tick's hits kill it this tick, while two more bullets arrive
+        let mut lethal = Weapon::Cannon.fire(
+            &config,
+            shooter,
+            None,
+            position,
+            BinAngle::zero(),
+            state.time,
+        );
+        lethal[0].damage = 1000;
+        state.tank_bullets.list[tank.0] = Some(lethal);
+        let from = position - Vector2D::new((3 * config.tank_size + 2) * GM_ONE_PIXEL, 0);
+        for weapon in [Weapon::Cannon, Weapon::HeavyShell].iter() {
+            for bullet in weapon.fire(&config, shooter, None, from, BinAngle::zero(), state.time) {
+                state.collision.add_bullet(&bullet, &config);
+                state.bullets.list.push(bullet);
+            }
+        }
+        let (state, events) = state.tick();
+        assert!(state.tanks[tank].is_none());
+        assert!(!events
+            .iter()
+            .any(|event| matches!(event, GameEvent::BulletHit { .. })));
+        let stats = &state.players[shooter].as_ref().unwrap().stats;
+        assert_eq!(stats.kills, 1);
+        assert_eq!((stats.shots_hit, stats.damage_dealt), (0, 0));
+        assert_consistent(&state);
+    }
+
+    #[test]
+    fn deltas_rebuild_snapshots() {
+        let mut state = arena();
+        let mut players = vec![
+            state.add_player("a".to_owned()),
+            state.add_player("b".to_owned()),
+        ];
+        let center = Point2D::new(400, 400) * GM_ONE_PIXEL;
+        let radius = 250 * GM_ONE_PIXEL;
+        let mut history = vec![];
+        let (mut reused, mut fired, mut bounced) = (false, false, false);
+        for tick in 0..600 {
+            // the leaving player's slot goes to the one joining, bumping its generation
+            if tick % 60 == 30 {
+                assert!(state.remove_player(&players.remove(0)).is_some());
+                players.push(state.add_player("c".to_owned()));
+            }
+            for player in &players {
+                state.players[*player].as_mut().unwrap().input = Input {
+                    fire: true,
+                    drive: Some(Drive::Forward),
+                    rotate: Some(Turn::Left),
+                    ..Default::default()
+                };
+            }
+            state = state.tick().0;
+            history.push((state.snapshot(), state.snapshot_around(center, radius)));
+            let (baseline, culled_baseline) = match history.len().checked_sub(6) {
+                Some(i) => &history[i],
+                None => continue,
+            };
+            let (snapshot, culled) = history.last().unwrap();
+            for (baseline, snapshot) in vec![(baseline, snapshot), (culled_baseline, culled)] {
+                let delta = snapshot.delta_from(baseline);
+                assert_eq!(baseline.apply_delta(&delta).as_ref(), Some(snapshot));
+            }
+            reused |= (baseline.players.generations.iter())
+                .zip(&snapshot.players.generations)
+                .any(|(old, new)| old != new);
+            let old: HashMap<_, _> = (baseline.bullets.list.iter())
+                .map(|bullet| (bullet.key(), bullet))
+                .collect();
+            for bullet in &snapshot.bullets.list {
+                match old.get(&bullet.key()) {
+                    Some(old) => bounced |= old.bounces != bullet.bounces,
+                    None => fired |= bullet.birth.0 > baseline.time.0,
+                }
+            }
+        }
+        assert!(reused && fired && bounced);
+    }
+}
@@ -1,5 +1,10 @@
 fn main() {
     println!("AAAA");
+    #[cfg(feature = "bench")]
+    if std::env::args().skip(1).next().as_deref() == Some("bench") {
+        tank_game::run_bench();
+        return;
+    }
     if (cfg!(feature = "server") && std::env::args().skip(1).next().as_deref() == Some("s"))
         || !cfg!(feature = "client")
     {
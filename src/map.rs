@@ -0,0 +1,120 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{BinAngle, Box2D, ElementList, Pixel, Point2D, Size2D, Wall, GM_SCALE};
+
+/// A map as written in a map file
+///
+/// Everything here is in pixels and degrees to keep map files readable, [`Level`] holds the
+/// same data converted to game units.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Map {
+    pub name: String,
+    /// The arena spans from the origin to `size`
+    pub size: Size2D<i64, Pixel>,
+    #[serde(default)]
+    pub walls: Vec<MapWall>,
+    #[serde(default)]
+    pub spawns: Vec<MapSpawn>,
+    #[serde(default)]
+    pub team_zones: Vec<TeamZone>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct MapWall {
+    pub center: Point2D<i64, Pixel>,
+    pub size: Size2D<i64, Pixel>,
+    #[serde(default)]
+    pub angle: f32,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct MapSpawn {
+    pub position: Point2D<i64, Pixel>,
+    #[serde(default)]
+    pub angle: f32,
+    #[serde(default)]
+    pub team: Option<u8>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct TeamZone {
+    pub team: u8,
+    pub area: Box2D<i64, Pixel>,
+}
+
+impl Map {
+    /// An empty arena with a single spawn point in the middle
+    pub fn empty(name: &str, size: Size2D<i64, Pixel>) -> Self {
+        Self {
+            name: name.to_owned(),
+            size,
+            walls: vec![],
+            spawns: vec![MapSpawn {
+                position: (size / 2).to_vector().to_point(),
+                angle: 0.0,
+                team: None,
+            }],
+            team_zones: vec![],
+        }
+    }
+    #[cfg(feature = "server")]
+    pub fn load(path: &std::path::Path) -> ron::Result<Self> {
+        ron::de::from_reader(std::fs::File::open(path)?)
+    }
+    pub fn arena(&self) -> Box2D {
+        Box2D::new(Point2D::zero(), self.size.to_vector().to_point()) * GM_SCALE
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SpawnPoint {
+    pub(crate) position: Point2D,
+    pub(crate) angle: BinAngle,
+    pub(crate) team: Option<u8>,
+}
+
+/// Static data for the map being played, built once from a [`Map`] and shared between states
+#[derive(Clone, Debug, Default)]
+pub struct Level {
+    pub(crate) name: String,
+    pub(crate) walls: ElementList<Wall>,
+    pub(crate) spawns: Vec<SpawnPoint>,
+    /// Each team and the area its tanks spawn in
+    pub(crate) team_zones: Vec<(u8, Box2D)>,
+}
+
+impl From<&Map> for Level {
+    fn from(map: &Map) -> Self {
+        let walls: Vec<_> = map
+            .walls
+            .iter()
+            .map(|w| {
+                Wall::rotated(
+                    w.center * GM_SCALE,
+                    w.size * GM_SCALE,
+                    BinAngle::degrees(w.angle),
+                )
+            })
+            .chain(Wall::boundary(map.arena()).iter().cloned())
+            .collect();
+        let spawns = map
+            .spawns
+            .iter()
+            .map(|s| SpawnPoint {
+                position: s.position * GM_SCALE,
+                angle: BinAngle::degrees(s.angle),
+                team: s.team,
+            })
+            .collect();
+        Self {
+            name: map.name.clone(),
+            walls: walls.into(),
+            spawns,
+            team_zones: map
+                .team_zones
+                .iter()
+                .map(|zone| (zone.team, zone.area * GM_SCALE))
+                .collect(),
+        }
+    }
+}
@@ -0,0 +1,89 @@
+use std::borrow::Cow;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    GameEvent, GameRules, Idx, Input, Map, Player, SimulationConfig, Snapshot, StateDelta, Time,
+};
+
+/// Bumped whenever a message changes shape, clients and servers only talk when theirs match
+pub const PROTOCOL_VERSION: u32 = 8;
+
+/// Most milliseconds a baseline can be behind the state built on it, past that the server sends
+/// the whole state instead, so clients only need to keep states this long
+pub const MAX_BASELINE_AGE: u32 = 1000;
+
+/// Everything a client sends
+///
+/// `Hello` has to stay the first variant with the same fields in every version, so a server can
+/// always read it and tell a client with a different version why it can't join.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum ClientMessage {
+    /// First message on a connection
+    Hello {
+        version: u32,
+        name: String,
+    },
+    Input(Input),
+    /// Time of the latest state the client has, the server sends changes against it from now on
+    Ack(Time),
+}
+
+/// Everything the server sends
+///
+/// `Rejected` has to stay the first variant with the same fields in every version, for the same
+/// reason as [`ClientMessage::Hello`].
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum ServerMessage<'a> {
+    /// Reply to a `Hello` the server can't accept, the connection is closed after it
+    Rejected { version: u32, reason: String },
+    /// Reply to an accepted `Hello`, with everything that stays the same for the whole match
+    Welcome {
+        player: Idx<'static, Player>,
+        map: Map,
+        config: SimulationConfig,
+        rules: GameRules,
+    },
+    /// The latest state and the server's hash of it
+    State { hash: u64, state: Cow<'a, Snapshot> },
+    /// The latest state as changes against one the client acknowledged, and the server's hash of
+    /// the result
+    Delta { hash: u64, delta: StateDelta },
+    /// What happened during the tick at `time`, only what the client can see
+    Events { time: Time, events: Vec<GameEvent> },
+}
+
+impl ClientMessage {
+    pub fn hello(name: String) -> Self {
+        Self::Hello {
+            version: PROTOCOL_VERSION,
+            name,
+        }
+    }
+}
+
+impl ServerMessage<'_> {
+    /// Why a client speaking `version` can't join, if it can't
+    pub fn check_version(version: u32) -> Option<Self> {
+        if version == PROTOCOL_VERSION {
+            return None;
+        }
+        Some(Self::Rejected {
+            version: PROTOCOL_VERSION,
+            reason: format!(
+                "server speaks protocol version {} but the client speaks version {}",
+                PROTOCOL_VERSION, version
+            ),
+        })
+    }
+}
+
+/// Encodes a message for the wire
+pub fn encode<T: Serialize>(message: &T) -> Vec<u8> {
+    rmp_serde::to_vec(message).unwrap()
+}
+
+/// Decodes a message from the wire, `None` if it isn't one
+pub fn decode<'de, T: Deserialize<'de>>(bytes: &'de [u8]) -> Option<T> {
+    rmp_serde::from_read_ref(bytes).ok()
+}
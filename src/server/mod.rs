@@ -1,4 +1,5 @@
-use std::collections::HashMap;
+use std::borrow::Cow;
+use std::collections::{HashMap, VecDeque};
 use std::mem;
 use std::net::SocketAddr;
 use std::sync::Arc;
@@ -8,6 +9,7 @@ use futures::{try_join, SinkExt, StreamExt};
 
 use parking_lot::Mutex;
 
+use tokio::sync::broadcast::{self, error::TryRecvError};
 use tokio::sync::{oneshot, watch};
 
 use euclid::{Angle, Box2D, Length, Point2D, Size2D, Vector2D};
@@ -15,98 +17,269 @@ use euclid::{Angle, Box2D, Length, Point2D, Size2D, Vector2D};
 use warp::ws::{self, WebSocket};
 use warp::Filter;
 
-use crate::{GameState, Idx, Input, Player, Tank};
+use crate::protocol::{self, ClientMessage, ServerMessage, MAX_BASELINE_AGE};
+use crate::{
+    flag_value, GameEvent, GameRules, GameState, Idx, Input, Level, Map, Player, SimulationConfig,
+    Snapshot, Tank, Time, GM_ONE_PIXEL,
+};
 
-struct SerializedGameState {
-    bytes: Vec<u8>,
+/// A tick's time and what happened during it
+type TickEvents = (Time, Arc<Vec<GameEvent>>);
+
+/// A snapshot of the state after a tick, shared by every connection that gets to see it
+///
+/// Snapshots go out with their hash so clients can tell when they have diverged from us.
+struct Tick {
+    snapshot: Snapshot,
+    hash: u64,
+}
+
+impl Tick {
+    fn of(snapshot: Snapshot, rules: &GameRules) -> Self {
+        Self {
+            hash: snapshot.content_hash(rules),
+            snapshot,
+        }
+    }
+    fn time(&self) -> u64 {
+        self.snapshot.time.0
+    }
+    /// The whole snapshot, for clients with nothing to build on
+    fn full(&self) -> ws::Message {
+        ws::Message::binary(protocol::encode(&ServerMessage::State {
+            hash: self.hash,
+            state: Cow::Borrowed(&self.snapshot),
+        }))
+    }
+    /// Only what changed since `baseline`
+    fn delta(&self, baseline: &Tick) -> ws::Message {
+        ws::Message::binary(protocol::encode(&ServerMessage::Delta {
+            hash: self.hash,
+            delta: self.snapshot.delta_from(&baseline.snapshot),
+        }))
+    }
+}
+
+/// What players get to see after a tick, built once in the tick loop rather than per connection
+struct Views {
+    /// The whole state, seen by everyone without a view radius
+    all: Arc<Tick>,
+    /// With a view radius, where each player's tank is and what is around it
+    around: HashMap<Idx<'static, Player>, (crate::Point2D, Arc<Tick>)>,
 }
 
-impl SerializedGameState {
-    fn into_message(&self) -> ws::Message {
-        ws::Message::binary(self.bytes.clone())
+impl Views {
+    /// With a view radius only what is near each tank is included, players waiting to respawn
+    /// see everything
+    fn of(state: &GameState, view_radius: Option<i64>) -> Self {
+        let all = Arc::new(Tick::of(state.snapshot(), &state.rules));
+        let around = match view_radius {
+            None => HashMap::new(),
+            Some(radius) => (&state.tanks)
+                .into_iter()
+                .filter_map(|(_, tank)| tank)
+                .map(|tank| {
+                    let snapshot = state.snapshot_around(tank.position, radius);
+                    let tick = Arc::new(Tick::of(snapshot, &state.rules));
+                    (tank.player, (tank.position, tick))
+                })
+                .collect(),
+        };
+        Self { all, around }
+    }
+    /// What `player` gets to see, and where it's centred if it doesn't cover everything
+    fn of_player(&self, player: Idx<'static, Player>) -> (Option<crate::Point2D>, Arc<Tick>) {
+        match self.around.get(&player) {
+            Some((center, tick)) => (Some(*center), tick.clone()),
+            None => (None, self.all.clone()),
+        }
     }
 }
 
+/// Most milliseconds of inputs a player can have waiting for their tick, when a client gets
+/// further ahead than this its oldest inputs are dropped
+const MAX_INPUT_BACKLOG: u32 = 250;
+
 struct Server {
     last_state: GameState,
+    /// Inputs that arrived for each player and haven't had their tick yet
+    queued: HashMap<Idx<'static, Player>, VecDeque<Input>>,
 }
 
 impl Server {
-    fn new() -> Self {
-        let state = GameState::new();
-        Self { last_state: state }
+    fn new(map: &Map, config: Arc<SimulationConfig>, rules: GameRules) -> Self {
+        let state = GameState::with_level(Arc::new(Level::from(map)), config, rules);
+        Self {
+            last_state: state,
+            queued: HashMap::new(),
+        }
     }
-    fn tick<I: Iterator<Item = (Idx<'static, Player>, Input)>>(&mut self, inputs: I) {
-        // take player inputs
+    fn tick<I: Iterator<Item = (Idx<'static, Player>, Input)>>(
+        &mut self,
+        inputs: I,
+    ) -> Vec<GameEvent> {
         for (player, input) in inputs {
-            if let Some(player) = self.last_state.players[player].as_mut() {
+            self.queued.entry(player).or_default().push_back(input);
+        }
+        // take one input per player, clients predict a tick for each input they send
+        let backlog = (self.last_state.config.ticks(MAX_INPUT_BACKLOG) as usize).max(1);
+        let players = &mut self.last_state.players;
+        self.queued.retain(|player, queue| {
+            // they may have left since sending it
+            let player = match players.get_mut(*player) {
+                Some(player) => player,
+                None => return false,
+            };
+            if queue.len() > backlog {
+                queue.drain(..queue.len() - backlog);
+            }
+            if let Some(input) = queue.pop_front() {
                 player.input = input;
             }
-        }
+            !queue.is_empty()
+        });
 
         // tick gamestate
-        let state = self.last_state.tick();
-        self.last_state = state;
+        self.last_state.advance()
+    }
+    fn log_event(&self, event: &GameEvent) {
+        let name = |player| {
+            self.last_state.players[player]
+                .as_ref()
+                .map(|p: &Player| p.name.as_str())
+                .unwrap_or("<gone>")
+        };
+        match event {
+            GameEvent::TankKilled { killer, victim, .. } => {
+                println!("PLAYER {} KILLED {}'S TANK", name(*killer), name(*victim))
+            }
+            GameEvent::PlayerJoined { name, .. } => println!("PLAYER {} JOINED", name),
+            GameEvent::PlayerLeft { player } => println!("PLAYER {:?} LEFT", player),
+            GameEvent::Respawned { player, .. } => println!("PLAYER {} RESPAWNED", name(*player)),
+            GameEvent::ShotFired { .. }
+            | GameEvent::BulletHit { .. }
+            | GameEvent::BulletsCollided { .. } => {}
+        }
     }
-}
-
-fn serialize(state: &GameState) -> SerializedGameState {
-    let bytes = rmp_serde::to_vec(state).unwrap();
-    SerializedGameState { bytes }
 }
 
 #[derive(Default)]
 pub struct PlayerInput {
-    new_connections: Vec<oneshot::Sender<Idx<'static, Player>>>,
+    /// Names of players joining and where to send their index
+    new_connections: Vec<(String, oneshot::Sender<Idx<'static, Player>>)>,
     disconnections: Vec<Idx<'static, Player>>,
-    inputs: HashMap<Idx<'static, Player>, Input>,
+    /// Inputs in the order they arrived, several can come from a player between ticks
+    inputs: Vec<(Idx<'static, Player>, Input)>,
 }
 
+/// Flags `run_server` understands, anything else starting with `--` is a mistake
+const FLAGS: [&str; 6] = [
+    "--config",
+    "--tick-rate",
+    "--teams",
+    "--view-radius",
+    "--bullets-collide",
+    "--friendly-fire",
+];
+
 pub fn run_server() {
-    let addr = std::env::args()
-        .skip(1)
-        .next()
-        .and_then(|x| x.parse().ok())
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if let Some(flag) = args
+        .iter()
+        .find(|arg| arg.starts_with("--") && !FLAGS.contains(&arg.as_str()))
+    {
+        panic!(
+            "Unknown flag {}, the server takes {}",
+            flag,
+            FLAGS.join(" ")
+        );
+    }
+    let addr = args
+        .iter()
+        .find_map(|x| x.parse().ok())
         .unwrap_or(([0, 0, 0, 0], 8999).into());
+    let config_path: Option<String> = flag_value(&args, "--config");
+    let mut config = match &config_path {
+        Some(path) => SimulationConfig::load(path.as_ref())
+            .unwrap_or_else(|e| panic!("Failed to load config {}: {}", path, e)),
+        // the same values as config/simulation.ron, built in
+        None => SimulationConfig::default(),
+    };
+    // ticks per second
+    if let Some(rate) = flag_value(&args, "--tick-rate") {
+        config.updates_per_second = rate;
+    }
+    if let Err(reason) = config.validate() {
+        panic!("Invalid config: {}", reason);
+    }
+    println!("Running at {} ticks per second", config.updates_per_second);
+    let config = Arc::new(config);
+    let ups = config.updates_per_second;
+    let map_path = args
+        .iter()
+        .find(|x| x.ends_with(".ron") && Some(*x) != config_path.as_ref());
+    let map = match map_path {
+        Some(path) => Map::load(path.as_ref())
+            .unwrap_or_else(|e| panic!("Failed to load map {}: {}", path, e)),
+        None => Map::empty("arena", Size2D::new(1920, 1080)),
+    };
+    println!("Loaded map {:?}", map.name);
+    let map = Arc::new(map);
+    let rules = GameRules {
+        bullets_collide: args.iter().any(|x| x == "--bullets-collide"),
+        teams: flag_value(&args, "--teams").unwrap_or(0),
+        friendly_fire: args.iter().any(|x| x == "--friendly-fire"),
+        ..GameRules::default()
+    };
+    // clients only hear about tanks and bullets this many pixels from their own tank
+    let view_radius = flag_value::<i64>(&args, "--view-radius").map(|r| r * GM_ONE_PIXEL);
 
     let rt = tokio::runtime::Runtime::new().unwrap();
     let guard = rt.enter();
-    let mut server = Server::new();
-    let (send, recv) = watch::channel(Arc::new(serialize(&server.last_state)));
+    let mut server = Server::new(&map, config.clone(), rules.clone());
+    let (send, recv) = watch::channel(Arc::new(Views::of(&server.last_state, view_radius)));
+    // every tick's events, kept for a second so clients that fall behind don't miss any
+    let (send_events, _) = broadcast::channel(ups as usize);
     let inputs = Arc::new(Mutex::new(PlayerInput::default()));
     let server_input = inputs.clone();
-    let mut interval = tokio::time::interval(Duration::from_secs(1) / 60);
-    rt.spawn(ws_server(addr, server_input.clone(), recv));
+    let mut interval = tokio::time::interval(config.tick_duration());
+    rt.spawn(ws_server(
+        addr,
+        server_input.clone(),
+        recv,
+        send_events.clone(),
+        map,
+        config,
+        rules,
+        view_radius,
+    ));
     let mut instant = Instant::now();
     let mut sum = Duration::new(0, 0);
     loop {
         // get inputs
         let loop_time = Instant::now();
         let inputs = mem::take(&mut *server_input.lock());
-        for send in inputs.new_connections {
-            let idx = server.last_state.players.push(Player {
-                name: "pog".to_owned(),
-                input: Default::default(),
-            });
+        for (name, send) in inputs.new_connections {
+            let idx = server.last_state.add_player(name);
             send.send(idx).unwrap();
-            server.last_state.tanks.push(Tank {
-                player: idx,
-                position: Point2D::zero(),
-                health: 100,
-                turret_angle: Angle::zero(),
-                angle: Angle::zero(),
-            });
         }
         for idx in inputs.disconnections.iter() {
-            // TODO
-            server.last_state.players.remove(idx);
+            server.last_state.remove_player(idx);
         }
-        server.tick(inputs.inputs.into_iter());
-        if server.last_state.time.0 % 60 == 0 {
+        let events = server.tick(inputs.inputs.into_iter());
+        for event in &events {
+            server.log_event(event);
+        }
+        // nobody is connected when this fails
+        let _ = send_events.send((server.last_state.time, Arc::new(events)));
+        if server.last_state.time.0 % ups as u64 == 0 {
             let new_instant = Instant::now();
-            println!("FPS: {}", 60.0 / (new_instant - instant).as_secs_f32());
+            println!(
+                "FPS: {}",
+                ups as f32 / (new_instant - instant).as_secs_f32()
+            );
             instant = new_instant;
-            println!("AVG MSPT: {}", ((sum).as_secs_f32() * 1000.0) / 60.0);
+            println!("AVG MSPT: {}", ((sum).as_secs_f32() * 1000.0) / ups as f32);
             sum = Duration::new(0, 0);
             println!("TANKS {:?}", server.last_state.tanks.list);
             println!("TANK_BULLETS: {:?}", server.last_state.tank_bullets.list);
@@ -114,11 +287,11 @@ pub fn run_server() {
             println!("PLAYERS {:?}", server.last_state.players.list);
             println!("TIME: {:?}", server.last_state.time);
         }
-        let ser = Arc::new(serialize(&server.last_state));
-        while let Err(_) = send.send(ser.clone()) {}
+        let views = Arc::new(Views::of(&server.last_state, view_radius));
+        while let Err(_) = send.send(views.clone()) {}
         let loop_end = Instant::now();
         sum += (loop_end - loop_time);
-        // delay to 60 ups
+        // delay to the configured ups
         rt.block_on(interval.tick());
     }
     //drop(guard);
@@ -127,13 +300,33 @@ pub fn run_server() {
 async fn ws_server(
     addr: SocketAddr,
     server_input: Arc<Mutex<PlayerInput>>,
-    watch: watch::Receiver<Arc<SerializedGameState>>,
+    watch: watch::Receiver<Arc<Views>>,
+    events: broadcast::Sender<TickEvents>,
+    map: Arc<Map>,
+    config: Arc<SimulationConfig>,
+    rules: GameRules,
+    view_radius: Option<i64>,
 ) {
     let routes = warp::path("stream").and(warp::ws()).map({
         move |ws: warp::ws::Ws| {
             let server_input = server_input.clone();
             let watch = watch.clone();
-            ws.max_send_queue(2).on_upgrade(move |websocket| handle_client(websocket, server_input, watch))
+            let events = events.subscribe();
+            let map = map.clone();
+            let config = config.clone();
+            let rules = rules.clone();
+            ws.max_send_queue(2).on_upgrade(move |websocket| {
+                handle_client(
+                    websocket,
+                    server_input,
+                    watch,
+                    events,
+                    map,
+                    config,
+                    rules,
+                    view_radius,
+                )
+            })
         }
     });
     warp::serve(routes).run(addr).await;
@@ -142,24 +335,70 @@ async fn ws_server(
 async fn handle_client(
     socket: WebSocket,
     global_input: Arc<Mutex<PlayerInput>>,
-    mut watch: watch::Receiver<Arc<SerializedGameState>>,
+    mut watch: watch::Receiver<Arc<Views>>,
+    mut events: broadcast::Receiver<TickEvents>,
+    map: Arc<Map>,
+    config: Arc<SimulationConfig>,
+    rules: GameRules,
+    view_radius: Option<i64>,
 ) {
     let (mut sink, mut stream) = socket.split();
+    let name = match stream.next().await {
+        Some(Ok(msg)) => match protocol::decode(msg.as_bytes()) {
+            Some(ClientMessage::Hello { version, name }) => {
+                if let Some(rejection) = ServerMessage::check_version(version) {
+                    println!("REJECTED CLIENT WITH PROTOCOL VERSION {}", version);
+                    let _ = sink
+                        .send(ws::Message::binary(protocol::encode(&rejection)))
+                        .await;
+                    let _ = sink.close().await;
+                    return;
+                }
+                name
+            }
+            _ => {
+                println!("CLIENT DIDN'T SAY HELLO");
+                return;
+            }
+        },
+        _ => return,
+    };
     let (send, recv) = oneshot::channel();
-    global_input.lock().new_connections.push(send);
-    println!("NEW PLAYER ID");
+    global_input.lock().new_connections.push((name, send));
     let player_idx = recv.await.unwrap();
-    println!("NEW PLAYER ID");
-    sink.send(ws::Message::binary(rmp_serde::to_vec(&player_idx).unwrap()))
+    println!("NEW PLAYER ID {:?}", player_idx);
+    let _leave = Leave {
+        input: global_input.clone(),
+        player: player_idx,
+    };
+    let welcome = ServerMessage::Welcome {
+        player: player_idx,
+        map: (*map).clone(),
+        config: (*config).clone(),
+        rules: rules.clone(),
+    };
+    if let Err(_) = sink
+        .send(ws::Message::binary(protocol::encode(&welcome)))
         .await
-        .unwrap();
-    let state = watch.borrow().clone().into_message();
-    sink.send(state).await.unwrap();
+    {
+        return;
+    }
+    let (_, tick) = watch.borrow().of_player(player_idx);
+    if let Err(_) = sink.send(tick.full()).await {
+        return;
+    }
+    // snapshots sent since the last one the client acknowledged, deltas are built against that one
+    let mut sent = VecDeque::from(vec![tick]);
+    let acked = Mutex::new(None::<Time>);
     // process player input
     let recv_input = async {
         while let Some(Ok(msg)) = stream.next().await {
-            if let Some(input) = parse_input_message(&msg) {
-                global_input.lock().inputs.insert(player_idx, input);
+            match protocol::decode(msg.as_bytes()) {
+                Some(ClientMessage::Input(input)) => {
+                    global_input.lock().inputs.push((player_idx, input));
+                }
+                Some(ClientMessage::Ack(time)) => *acked.lock() = Some(time),
+                _ => {}
             }
         }
         Err::<(), ()>(())
@@ -169,15 +408,124 @@ async fn handle_client(
     let send_state = async {
         while let Ok(()) = {
             watch.changed().await.map_err(|_| ())?;
-            let state = watch.borrow().clone().into_message();
-            sink.send(state).await
+            let (center, tick) = watch.borrow().of_player(player_idx);
+            // events go out ahead of the state they happened in
+            loop {
+                let (time, tick_events) = match events.try_recv() {
+                    Ok(tick_events) => tick_events,
+                    Err(TryRecvError::Lagged(ticks)) => {
+                        println!("PLAYER {:?} MISSED {} TICKS OF EVENTS", player_idx, ticks);
+                        continue;
+                    }
+                    Err(_) => break,
+                };
+                let tick_events = view_events(&tick_events, center, view_radius);
+                if !tick_events.is_empty() {
+                    let message = ServerMessage::Events {
+                        time,
+                        events: tick_events,
+                    };
+                    sink.send(ws::Message::binary(protocol::encode(&message)))
+                        .await
+                        .map_err(|_| ())?;
+                }
+            }
+            let max_age = config.ticks(MAX_BASELINE_AGE) as u64;
+            let message = match baseline(&mut sent, *acked.lock(), &tick, max_age) {
+                Some(baseline) => tick.delta(baseline),
+                None => tick.full(),
+            };
+            sent.push_back(tick);
+            sink.send(message).await
         } {}
         Err::<(), ()>(())
     };
     let _ = try_join!(recv_input, send_state);
-    global_input.lock().disconnections.push(player_idx);
 }
 
-fn parse_input_message(msg: &ws::Message) -> Option<Input> {
-    rmp_serde::from_read_ref(msg.as_bytes()).ok()
+/// Takes a player out of the game when dropped, however their connection ends
+struct Leave {
+    input: Arc<Mutex<PlayerInput>>,
+    player: Idx<'static, Player>,
+}
+
+impl Drop for Leave {
+    fn drop(&mut self) {
+        self.input.lock().disconnections.push(self.player);
+    }
+}
+
+/// The events a player gets to hear about, with a view radius only those near `center`
+fn view_events(
+    events: &[GameEvent],
+    center: Option<crate::Point2D>,
+    view_radius: Option<i64>,
+) -> Vec<GameEvent> {
+    events
+        .iter()
+        .filter(|event| match (view_radius, center, event.position()) {
+            (Some(radius), Some(center), Some(position)) => {
+                (position - center).square_length() <= radius * radius
+            }
+            _ => true,
+        })
+        .cloned()
+        .collect()
+}
+
+/// The acknowledged snapshot to build `tick` on, if it's at most `max_age` ticks older
+///
+/// Drops every sent snapshot before the acknowledged one, the client won't be asked to build on
+/// those again.
+fn baseline<'a>(
+    sent: &'a mut VecDeque<Arc<Tick>>,
+    acked: Option<Time>,
+    tick: &Tick,
+    max_age: u64,
+) -> Option<&'a Tick> {
+    if let Some(acked) = acked {
+        while sent
+            .front()
+            .map(|sent| sent.time() < acked.0)
+            .unwrap_or(false)
+        {
+            sent.pop_front();
+        }
+    }
+    while sent.len() > max_age as usize {
+        sent.pop_front();
+    }
+    sent.front()
+        .filter(|baseline| Some(baseline.time()) == acked.map(|time| time.0))
+        .filter(|baseline| tick.time() - baseline.time() <= max_age)
+        .map(|baseline| &**baseline)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn inputs_arriving_together_get_a_tick_each() {
+        let map = Map::empty("queue", Size2D::new(800, 800));
+        let mut server = Server::new(&map, Default::default(), Default::default());
+        let player = server.last_state.add_player("a".to_owned());
+        let burst: Vec<_> = (1..=3)
+            .map(|seq| {
+                let input = Input {
+                    seq,
+                    ..Default::default()
+                };
+                (player, input)
+            })
+            .collect();
+        let mut applied = vec![];
+        server.tick(burst.into_iter());
+        for _ in 0..4 {
+            let input = &server.last_state.players[player].as_ref().unwrap().input;
+            applied.push(input.seq);
+            server.tick(std::iter::empty());
+        }
+        assert_eq!(applied, vec![1, 2, 3, 3]);
+    }
 }
@@ -0,0 +1,186 @@
+use serde::{Deserialize, Serialize};
+
+use crate::hash::{StableHash, StateHasher};
+use crate::{BinAngle, Bullet, Idx, Player, Point2D, SimulationConfig, Time, GM_ONE_PIXEL};
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Weapon {
+    Cannon,
+    MachineGun,
+    /// Several pellets fanned out over a short range
+    Shotgun,
+    /// Slow shell that damages every tank close to where it lands
+    HeavyShell,
+}
+
+impl Default for Weapon {
+    fn default() -> Self {
+        Weapon::Cannon
+    }
+}
+
+/// How a weapon and the bullets it fires behave, part of the [`SimulationConfig`]
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct WeaponSpec {
+    /// Bullet speed in pixels per second
+    pub speed: i64,
+    /// Milliseconds before a bullet that hasn't hit anything disappears
+    pub lifetime: u32,
+    pub damage: i64,
+    /// Milliseconds between shots
+    pub cooldown: u32,
+    pub magazine_size: u32,
+    /// Milliseconds to refill an empty magazine
+    pub reload_time: u32,
+    /// Bullets fired per shot
+    pub pellets: u32,
+    /// Degrees the pellets are spread over
+    pub spread: i64,
+    /// Radius in pixels around an impact that takes `splash_damage`, 0 for no splash
+    pub splash_radius: i64,
+    pub splash_damage: i64,
+    /// Times a bullet bounces off walls before stopping at one
+    pub bounces: u32,
+}
+
+impl Weapon {
+    pub const ALL: [Weapon; 4] = [
+        Weapon::Cannon,
+        Weapon::MachineGun,
+        Weapon::Shotgun,
+        Weapon::HeavyShell,
+    ];
+    /// Bullets for a single shot from a turret at `position` facing `angle`
+    pub(crate) fn fire(
+        self,
+        config: &SimulationConfig,
+        player: Idx<'static, Player>,
+        team: Option<u8>,
+        position: Point2D,
+        angle: BinAngle,
+        time: Time,
+    ) -> Vec<Bullet> {
+        let spec = config.weapon(self);
+        // clear of the corners of our own hitbox, however big tanks are
+        let muzzle = position + angle.vector(config.tank_size * 2 * GM_ONE_PIXEL);
+        (0..spec.pellets)
+            .map(|i| {
+                let spread = spec.spread * 65536 / 360;
+                let offset = match spec.pellets as i64 {
+                    1 => 0,
+                    n => spread * i as i64 / (n - 1) - spread / 2,
+                };
+                Bullet {
+                    position: muzzle,
+                    angle: angle + BinAngle(offset as u16),
+                    damage: spec.damage,
+                    birth: time,
+                    player,
+                    team,
+                    kind: self,
+                    bounces: spec.bounces,
+                    pellet: i,
+                }
+            })
+            .collect()
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct WeaponState {
+    kind: Weapon,
+    /// Ticks until the next shot can be fired
+    cooldown: u32,
+    /// Shells left in the magazine
+    ammo: u32,
+    /// Shells in a full magazine
+    magazine_size: u32,
+    /// Ticks until the magazine is full again, if reloading
+    reload: Option<u32>,
+    /// Ticks a whole reload takes
+    reload_time: u32,
+}
+
+impl StableHash for WeaponState {
+    fn stable_hash(&self, hasher: &mut StateHasher) {
+        let WeaponState {
+            kind,
+            cooldown,
+            ammo,
+            magazine_size,
+            reload,
+            reload_time,
+        } = self;
+        kind.stable_hash(hasher);
+        cooldown.stable_hash(hasher);
+        ammo.stable_hash(hasher);
+        magazine_size.stable_hash(hasher);
+        reload.stable_hash(hasher);
+        reload_time.stable_hash(hasher);
+    }
+}
+
+impl WeaponState {
+    pub(crate) fn full(kind: Weapon, config: &SimulationConfig) -> Self {
+        let spec = config.weapon(kind);
+        Self {
+            kind,
+            cooldown: 0,
+            ammo: spec.magazine_size,
+            magazine_size: spec.magazine_size,
+            reload: None,
+            reload_time: config.ticks(spec.reload_time),
+        }
+    }
+    pub fn kind(&self) -> Weapon {
+        self.kind
+    }
+    /// Advances the weapon by a tick, returning whether it fired
+    ///
+    /// Asking for a different weapon swaps to it empty, so it has to be reloaded before firing.
+    pub(crate) fn tick(
+        &self,
+        config: &SimulationConfig,
+        fire: bool,
+        select: Weapon,
+    ) -> (Self, bool) {
+        if select != self.kind {
+            let full = Self::full(select, config);
+            let weapon = Self {
+                ammo: 0,
+                reload: Some(full.reload_time),
+                ..full
+            };
+            return (weapon, false);
+        }
+        let mut weapon = Self {
+            cooldown: self.cooldown.saturating_sub(1),
+            ..self.clone()
+        };
+        match self.reload {
+            Some(ticks) if ticks > 1 => {
+                weapon.reload = Some(ticks - 1);
+                return (weapon, false);
+            }
+            Some(_) => weapon = Self::full(self.kind, config),
+            None => {}
+        }
+        if fire && weapon.cooldown == 0 && weapon.ammo > 0 {
+            weapon.ammo -= 1;
+            weapon.cooldown = config.ticks(config.weapon(self.kind).cooldown);
+            if weapon.ammo == 0 {
+                weapon.reload = Some(weapon.reload_time);
+            }
+            (weapon, true)
+        } else {
+            (weapon, false)
+        }
+    }
+    /// How full the magazine is, from 0 to 1, counting reload progress while empty
+    pub fn magazine_fraction(&self) -> f32 {
+        match self.reload {
+            Some(ticks) => 1.0 - ticks as f32 / self.reload_time.max(1) as f32,
+            None => self.ammo as f32 / self.magazine_size.max(1) as f32,
+        }
+    }
+}